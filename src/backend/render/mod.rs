@@ -95,6 +95,7 @@ pub struct IndicatorShader(pub GlesPixelProgram);
 pub enum Key {
     Static(Id),
     Group(Weak<()>),
+    GroupLabel(Weak<()>, usize),
     Window(CosmicMapped),
 }
 impl std::hash::Hash for Key {
@@ -102,6 +103,10 @@ impl std::hash::Hash for Key {
         match self {
             Key::Static(id) => id.hash(state),
             Key::Group(arc) => (arc.as_ptr() as usize).hash(state),
+            Key::GroupLabel(arc, idx) => {
+                (arc.as_ptr() as usize).hash(state);
+                idx.hash(state);
+            }
             Key::Window(window) => window.hash(state),
         }
     }
@@ -111,6 +116,7 @@ impl PartialEq for Key {
         match (self, other) {
             (Key::Static(s1), Key::Static(s2)) => s1 == s2,
             (Key::Group(g1), Key::Group(g2)) => Weak::ptr_eq(g1, g2),
+            (Key::GroupLabel(g1, i1), Key::GroupLabel(g2, i2)) => Weak::ptr_eq(g1, g2) && i1 == i2,
             (Key::Window(w1), Key::Window(w2)) => w1 == w2,
             _ => false,
         }
@@ -199,7 +205,7 @@ impl IndicatorShader {
         let mut cache = user_data.get::<IndicatorCache>().unwrap().borrow_mut();
         cache.retain(|k, _| match k {
             Key::Static(_) => true,
-            Key::Group(w) => w.upgrade().is_some(),
+            Key::Group(w) | Key::GroupLabel(w, _) => w.upgrade().is_some(),
             Key::Window(w) => w.alive(),
         });
 
@@ -280,7 +286,7 @@ impl BackdropShader {
         let mut cache = user_data.get::<BackdropCache>().unwrap().borrow_mut();
         cache.retain(|k, _| match k {
             Key::Static(_) => true,
-            Key::Group(a) => a.upgrade().is_some(),
+            Key::Group(a) | Key::GroupLabel(a, _) => a.upgrade().is_some(),
             Key::Window(w) => w.alive(),
         });
 
@@ -554,6 +560,8 @@ where
                     overview.clone(),
                     resize_indicator.clone(),
                     state.config.static_conf.active_hint,
+                    state.config.static_conf.tiling_backdrop,
+                    state.config.static_conf.tiling_group_labels,
                 )
                 .map_err(|_| OutputNoMode)?;
             elements.extend(p_elements.into_iter().map(|p_element| {
@@ -610,6 +618,8 @@ where
             overview,
             resize_indicator,
             state.config.static_conf.active_hint,
+            state.config.static_conf.tiling_backdrop,
+            state.config.static_conf.tiling_group_labels,
         )
         .map_err(|_| OutputNoMode)?;
     elements.extend(p_elements.into_iter().map(|p_element| {
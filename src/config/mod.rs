@@ -47,6 +47,10 @@ pub struct StaticConfig {
     pub active_hint: u8,
     #[serde(default = "default_gaps")]
     pub gaps: (u8, u8),
+    #[serde(default)]
+    pub tiling_backdrop: Option<[f32; 4]>,
+    #[serde(default)]
+    pub tiling_group_labels: bool,
 }
 
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -380,6 +384,8 @@ impl Config {
             tiling_enabled: false,
             active_hint: default_active_hint(),
             gaps: default_gaps(),
+            tiling_backdrop: None,
+            tiling_group_labels: false,
         }
     }
 
@@ -1042,6 +1048,7 @@ pub enum Action {
 
     Focus(FocusDirection),
     Move(Direction),
+    MoveToEdge(Direction),
 
     ToggleOrientation,
     Orientation(crate::shell::layout::Orientation),
@@ -7,7 +7,7 @@ use crate::{
         grabs::ResizeEdge,
         layout::{
             floating::SeatMoveGrabState,
-            tiling::{Direction, FocusResult, MoveResult},
+            tiling::{Direction, FocusResult, MoveResult, StackOrder},
         },
         OverviewMode, ResizeDirection, ResizeMode, Workspace,
     }, // shell::grabs::SeatMoveGrabState
@@ -1221,6 +1221,15 @@ impl State {
                     }
                 }
             }
+            Action::MoveToEdge(direction) => {
+                let current_output = seat.active_output();
+                let workspace = self.common.shell.active_space_mut(&current_output);
+                if workspace.get_fullscreen(&current_output).is_some() {
+                    return;
+                }
+
+                workspace.tiling_layer.move_to_edge(direction, seat);
+            }
             Action::Maximize => {
                 let current_output = seat.active_output();
                 let workspace = self.common.shell.active_space_mut(&current_output);
@@ -1245,20 +1254,25 @@ impl State {
             Action::ToggleOrientation => {
                 let output = seat.active_output();
                 let workspace = self.common.shell.active_space_mut(&output);
-                workspace.tiling_layer.update_orientation(None, &seat);
+                workspace.tiling_layer.update_orientation(None, &seat, false);
             }
             Action::Orientation(orientation) => {
                 let output = seat.active_output();
                 let workspace = self.common.shell.active_space_mut(&output);
                 workspace
                     .tiling_layer
-                    .update_orientation(Some(orientation), &seat);
+                    .update_orientation(Some(orientation), &seat, false);
             }
             Action::ToggleStacking => {
                 let output = seat.active_output();
                 let workspace = self.common.shell.active_space_mut(&output);
                 let focus_stack = workspace.focus_stack.get_mut(seat);
-                workspace.tiling_layer.toggle_stacking(seat, focus_stack);
+                if let Some(new_focus) = workspace
+                    .tiling_layer
+                    .toggle_stacking(seat, focus_stack, StackOrder::Spatial)
+                {
+                    Common::set_focus(self, Some(&new_focus), seat, None);
+                }
             }
             Action::ToggleTiling => {
                 let output = seat.active_output();
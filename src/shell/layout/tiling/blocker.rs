@@ -57,6 +57,18 @@ impl TilingBlocker {
         self.signaled.load(Ordering::SeqCst)
     }
 
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(self.start)
+    }
+
+    /// Surfaces, which did not ack their configure yet
+    pub fn unacked(&self) -> impl Iterator<Item = &CosmicSurface> {
+        self.necessary_acks
+            .iter()
+            .filter(|(surf, serial)| !surf.serial_acked(serial))
+            .map(|(surf, _)| surf)
+    }
+
     #[must_use]
     pub fn signal_ready(&self) -> HashMap<ClientId, Client> {
         self.ready.swap(true, Ordering::SeqCst);
@@ -149,31 +149,19 @@ impl PointerGrab<State> for ResizeForkGrab {
                         Data::Group {
                             sizes, orientation, ..
                         } => {
+                            let min_length =
+                                tiling_layer.config.min_length_on(*orientation, &output);
                             if sizes[self.left_up_idx] + sizes[self.left_up_idx + 1]
-                                < match orientation {
-                                    Orientation::Vertical => 720,
-                                    Orientation::Horizontal => 480,
-                                }
+                                < min_length * 2
                             {
                                 return;
                             };
 
                             let old_size = sizes[self.left_up_idx];
-                            sizes[self.left_up_idx] = (old_size + delta).max(
-                                if self.orientation == Orientation::Vertical {
-                                    360
-                                } else {
-                                    240
-                                },
-                            );
+                            sizes[self.left_up_idx] = (old_size + delta).max(min_length);
                             let diff = old_size - sizes[self.left_up_idx];
                             let next_size = sizes[self.left_up_idx + 1] + diff;
-                            sizes[self.left_up_idx + 1] =
-                                next_size.max(if self.orientation == Orientation::Vertical {
-                                    360
-                                } else {
-                                    240
-                                });
+                            sizes[self.left_up_idx + 1] = next_size.max(min_length);
                             let next_diff = next_size - sizes[self.left_up_idx + 1];
                             sizes[self.left_up_idx] += next_diff;
                         }
@@ -181,7 +169,14 @@ impl PointerGrab<State> for ResizeForkGrab {
                     }
 
                     self.last_loc = event.location;
-                    let blocker = TilingLayout::update_positions(&output, tree, tiling_layer.gaps);
+                    let blocker = TilingLayout::update_positions(
+                        &output,
+                        tree,
+                        &tiling_layer.config,
+                        tiling_layer.geometry_hook.as_ref(),
+                        queue.overscan,
+                        queue.reserved_zone,
+                    );
                     tiling_layer.pending_blockers.extend(blocker);
                 } else {
                     handle.unset_grab(data, event.serial, event.time);
@@ -29,25 +29,27 @@ use smithay::{
     backend::renderer::{
         element::{
             utils::{CropRenderElement, Relocate, RelocateRenderElement, RescaleRenderElement},
-            AsRenderElements, RenderElement,
+            AsRenderElements, Id, RenderElement,
         },
         ImportAll, ImportMem, Renderer,
     },
     desktop::{layer_map_for_output, space::SpaceElement, PopupKind},
     input::Seat,
     output::Output,
-    reexports::wayland_server::Client,
-    utils::{IsAlive, Logical, Point, Rectangle, Scale},
+    reexports::{calloop::LoopHandle, wayland_server::Client},
+    utils::{IsAlive, Logical, Physical, Point, Rectangle, Scale, Size},
     wayland::{compositor::add_blocker, seat::WaylandFocus},
 };
 use std::{
     borrow::Borrow,
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
     hash::Hash,
-    sync::{Arc, Weak},
+    ops::Range,
+    sync::{Arc, Mutex, Weak},
     time::{Duration, Instant},
 };
-use tracing::trace;
+use tracing::{trace, warn};
 use wayland_backend::server::ClientId;
 
 mod blocker;
@@ -56,11 +58,24 @@ pub use self::blocker::*;
 pub use self::grabs::*;
 
 pub const ANIMATION_DURATION: Duration = Duration::from_millis(200);
+const RESIZE_BASE_STEP: f64 = 10.0;
+const RESIZE_MAX_STEP: f64 = 120.0;
+const RESIZE_ACCELERATION: f64 = 1.15;
+const RESIZE_ACCEL_TIMEOUT: Duration = Duration::from_millis(300);
+const FOCUS_HISTORY_LEN: usize = 32;
+const CHANGE_LOG_LEN: usize = 256;
+const AUTO_BALANCE_THRESHOLD: f64 = 0.1;
+const AUTO_BALANCE_STEP: f64 = 0.2;
+const GROUP_MAXIMIZE_SLIVER: i32 = 16;
+const TEMPLATE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone)]
 struct OutputData {
     output: Output,
     location: Point<i32, Logical>,
+    backdrop_id: Id,
+    drop_hint_id: Id,
+    preselect_id: Id,
 }
 
 impl Borrow<Output> for OutputData {
@@ -123,10 +138,278 @@ pub enum MoveResult {
     ShiftFocus(KeyboardFocusTarget),
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropHint {
+    pub node: NodeId,
+    pub idx: usize,
+    pub orientation: Orientation,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// Windows are split according to focus and user input
+    #[default]
+    Manual,
+    /// The first four windows fill the quadrants of the output, any further windows are stacked onto them
+    Quadrant,
+    /// New windows open as equally sized columns, explicit up/down splits add rows to a column
+    Columns,
+}
+
+/// How space is made for windows added to an existing group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizingPolicy {
+    /// All children get the same length
+    #[default]
+    EqualLength,
+    /// Windows get roughly the same area, regardless of how deeply they are nested
+    EqualArea,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueryMode {
+    /// Spatial queries use the geometries the layout is transitioning to
+    #[default]
+    Target,
+    /// Spatial queries use the geometries currently visible on screen, including animations
+    Visible,
+}
+
+/// Semantic layout changes, see `TilingLayout::drain_changes`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutChange {
+    WindowMapped {
+        output: Output,
+        node: NodeId,
+        window: CosmicMapped,
+    },
+    WindowUnmapped {
+        output: Output,
+        window: CosmicMapped,
+    },
+    /// The window changed its node or parent
+    WindowMoved {
+        output: Output,
+        node: NodeId,
+        window: CosmicMapped,
+    },
+    GroupCreated {
+        output: Output,
+        node: NodeId,
+    },
+    /// `node` refers to the tree before the removal
+    GroupRemoved {
+        output: Output,
+        node: NodeId,
+    },
+    SizesChanged {
+        output: Output,
+        node: NodeId,
+        sizes: Vec<i32>,
+    },
+    OrientationChanged {
+        output: Output,
+        node: NodeId,
+        orientation: Orientation,
+    },
+}
+
+// shared by a `TilingLayout` and its queues, oldest first, bounded by CHANGE_LOG_LEN
+#[derive(Debug, Clone, Default)]
+struct ChangeLog(Arc<Mutex<VecDeque<LayoutChange>>>);
+
+impl ChangeLog {
+    fn record(&self, changes: impl IntoIterator<Item = LayoutChange>) {
+        let mut log = self.0.lock().unwrap();
+        for change in changes {
+            if log.len() >= CHANGE_LOG_LEN {
+                log.pop_front();
+            }
+            log.push_back(change);
+        }
+    }
+}
+
+/// Committed node geometries of an output, see `TilingLayout::changed_nodes_since`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot(HashMap<NodeId, Rectangle<i32, Logical>>);
+
+/// Tab order of a stack created from a group by `TilingLayout::toggle_stacking`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StackOrder {
+    /// Tree order
+    #[default]
+    Spatial,
+    /// Most recently focused first
+    FocusOrder,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderOrder {
+    /// Activated windows are rendered on top of all others
+    #[default]
+    ActiveLast,
+    /// Windows are rendered strictly in tree order
+    TreeOrder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Left or top
+    First,
+    /// Right or bottom
+    Last,
+}
+
+/// Zooms and pans the group overview, in output local coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTransform {
+    pub scale: f64,
+    pub translation: Point<i32, Logical>,
+}
+
+impl ViewTransform {
+    fn apply(&self, rect: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size(
+            rect.loc.to_f64().upscale(self.scale).to_i32_round() + self.translation,
+            rect.size.to_f64().upscale(self.scale).to_i32_round(),
+        )
+    }
+
+    fn invert(&self, point: Point<f64, Logical>) -> Point<f64, Logical> {
+        (point - self.translation.to_f64()).downscale(self.scale)
+    }
+}
+
+/// Remembers where a window was tiled before `TilingLayout::float_out`
+#[derive(Debug, Clone)]
+pub struct FloatTicket {
+    window: CosmicMapped,
+    output: Output,
+    parent: Option<NodeId>,
+    idx: usize,
+    orientation: Orientation,
+    // neighbor and whether the window came after it
+    sibling: Option<(NodeId, bool)>,
+}
+
+/// The windows passed to `TilingLayout::set_pair_ratio` aren't the only children of one group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotAPair;
+
+/// Part of a stack under the pointer, see `TilingLayout::stack_hit_under`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackHit {
+    /// The tab bar
+    Header,
+    /// The content of the active tab
+    Surface(CosmicSurface),
+}
+
+/// Layout preset realized by `TilingLayout::apply_template`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutTemplate {
+    /// Taken by the next window of this app id
+    Slot(String),
+    /// Children with their share of the group
+    Group(Orientation, Vec<(f64, LayoutTemplate)>),
+}
+
+impl LayoutTemplate {
+    // app ids of all slots, in pre-order
+    fn slots(&self) -> Vec<&str> {
+        match self {
+            LayoutTemplate::Slot(app_id) => vec![app_id.as_str()],
+            LayoutTemplate::Group(_, children) => children
+                .iter()
+                .flat_map(|(_, child)| child.slots())
+                .collect(),
+        }
+    }
+
+    // groups from the root down to the `slot`th slot with their orientation,
+    // the share and slot range of every child and which child contains the slot
+    fn slot_path(&self, slot: usize) -> Vec<(Orientation, Vec<(f64, Range<usize>)>, usize)> {
+        let mut path = Vec::new();
+        let mut template = self;
+        let mut start = 0;
+        while let LayoutTemplate::Group(orientation, children) = template {
+            let mut ranges = Vec::new();
+            for (ratio, child) in children {
+                let len = child.slots().len();
+                ranges.push((ratio.max(f64::EPSILON), start..start + len));
+                start += len;
+            }
+            let Some(idx) = ranges.iter().position(|(_, range)| range.contains(&slot)) else {
+                break;
+            };
+            start = ranges[idx].1.start;
+            template = &children[idx].1;
+            path.push((*orientation, ranges, idx));
+        }
+        path
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingTemplate {
+    template: LayoutTemplate,
+    // slot index -> window
+    filled: HashMap<usize, CosmicMapped>,
+    deadline: Instant,
+}
+
+/// Identifies a slot handed out by `TilingLayout::reserve_split`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReservationToken(u64);
+
+#[derive(Debug, Clone)]
+struct Reservation {
+    output: Output,
+    node: Option<(NodeId, ReservedNode)>,
+    direction: Direction,
+}
+
+// what the reserved node held, node ids get reused once the node is removed
+#[derive(Debug, Clone)]
+enum ReservedNode {
+    Window(CosmicMapped),
+    Group(Weak<()>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ResizeAccel {
+    last_resize: Instant,
+    velocity: f64,
+}
+
 #[derive(Debug, Clone, Default)]
 struct TreeQueue {
     trees: VecDeque<(Tree<Data>, Duration, Option<TilingBlocker>)>,
     animation_start: Option<Instant>,
+    overscan: (i32, i32, i32, i32),
+    // edge and fraction of the tiling area kept free of the tree
+    reserved_zone: Option<(Direction, f64)>,
+    overview_transform: Option<ViewTransform>,
+    // one-shot override for the orientation of the next split
+    next_split: Option<Orientation>,
+    // most recent first, `focus_cursor` points at the current entry
+    focus_history: VecDeque<CosmicMapped>,
+    focus_cursor: usize,
+    // used by `element_under` to keep hitting a fork within the hysteresis band
+    last_fork_hit: Arc<Mutex<Option<ResizeForkTarget>>>,
+    // last focused node seen while rendering and when it got focus
+    focus_pulse: Arc<Mutex<Option<(NodeId, Instant)>>>,
+    // time of the last `push_tree`, to debounce animations
+    pending_since: Option<Instant>,
+    // node split by the next mapped window and on which side, see `TilingLayout::preselect`
+    preselect: Option<(NodeId, Direction)>,
+    // group maximized by `TilingLayout::toggle_group_maximize` and its previous sizes
+    group_maximize: Option<(NodeId, Vec<i32>)>,
+    // see `TilingLayout::apply_template`
+    template: Option<PendingTemplate>,
+    // the queue's output and where `push_tree` records the changes of every pushed tree
+    change_log: Option<(Output, ChangeLog)>,
 }
 
 impl TreeQueue {
@@ -136,17 +419,323 @@ impl TreeQueue {
         duration: impl Into<Option<Duration>>,
         blocker: Option<TilingBlocker>,
     ) {
+        if let (Some((output, log)), Some((old, _, _))) = (&self.change_log, self.trees.back()) {
+            log.record(TilingLayout::tree_changes(output, old, &tree));
+        }
         self.trees
-            .push_back((tree, duration.into().unwrap_or(Duration::ZERO), blocker))
+            .push_back((tree, duration.into().unwrap_or(Duration::ZERO), blocker));
+        self.pending_since = Some(Instant::now());
+    }
+
+    // drops `mapped` from the focus history, keeping the cursor on the same entry if possible
+    fn forget_focus(&mut self, mapped: &CosmicMapped) {
+        let Some(idx) = self.focus_history.iter().position(|m| m == mapped) else { return };
+        self.focus_history.remove(idx);
+        if idx < self.focus_cursor {
+            self.focus_cursor -= 1;
+        }
+    }
+
+    fn animation_percentage(&self, curve: AnimationCurve) -> Option<f32> {
+        let animation_start = self.animation_start?;
+        let duration = self.trees.get(1)?.1;
+        let percentage = Instant::now().duration_since(animation_start).as_millis() as f32
+            / duration.as_millis() as f32;
+        Some(curve.apply(percentage))
+    }
+
+    /// The tree as it is currently displayed, with geometries interpolated during animations
+    fn visible_tree(&self, curve: AnimationCurve) -> Tree<Data> {
+        let Some(percentage) = self.animation_percentage(curve) else {
+            return self.trees.front().unwrap().0.copy_clone();
+        };
+        let reference_tree = &self.trees.front().unwrap().0;
+        let mut tree = self.trees.get(1).unwrap().0.copy_clone();
+
+        if let Some(root_id) = tree.root_node_id().cloned() {
+            for node_id in tree
+                .traverse_pre_order_ids(&root_id)
+                .unwrap()
+                .collect::<Vec<_>>()
+                .into_iter()
+            {
+                let Ok(old_node) = reference_tree.get(&node_id) else { continue };
+                let old_geo = *old_node.data().geometry();
+                let data = tree.get_mut(&node_id).unwrap().data_mut();
+                let new_geo = *data.geometry();
+                data.update_geometry(interpolate_geometry(old_geo, new_geo, percentage));
+            }
+        }
+
+        tree
+    }
+}
+
+fn interpolate_geometry(
+    old_geo: Rectangle<i32, Logical>,
+    new_geo: Rectangle<i32, Logical>,
+    percentage: f32,
+) -> Rectangle<i32, Logical> {
+    Rectangle::from_loc_and_size(
+        (
+            old_geo.loc.x + ((new_geo.loc.x - old_geo.loc.x) as f32 * percentage).round() as i32,
+            old_geo.loc.y + ((new_geo.loc.y - old_geo.loc.y) as f32 * percentage).round() as i32,
+        ),
+        (
+            old_geo.size.w + ((new_geo.size.w - old_geo.size.w) as f32 * percentage).round() as i32,
+            old_geo.size.h + ((new_geo.size.h - old_geo.size.h) as f32 * percentage).round() as i32,
+        ),
+    )
+}
+
+type GeometryHookFn = dyn Fn(&CosmicMapped, Rectangle<i32, Logical>) -> Rectangle<i32, Logical>;
+
+#[derive(Clone)]
+pub struct GeometryHook(Arc<GeometryHookFn>);
+
+impl fmt::Debug for GeometryHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("GeometryHook").finish()
+    }
+}
+
+type StackFactoryFn =
+    dyn Fn(Vec<CosmicSurface>, LoopHandle<'static, crate::state::Data>) -> CosmicStack;
+
+/// Builds the stack for windows stacked together, `CosmicStack::new` by default
+#[derive(Clone)]
+pub struct StackFactory(Arc<StackFactoryFn>);
+
+impl fmt::Debug for StackFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StackFactory").finish()
+    }
+}
+
+fn build_stack(
+    factory: Option<&StackFactory>,
+    surfaces: Vec<CosmicSurface>,
+    handle: LoopHandle<'static, crate::state::Data>,
+) -> CosmicMapped {
+    match factory {
+        Some(factory) => (factory.0)(surfaces, handle).into(),
+        None => CosmicStack::new(surfaces.into_iter(), handle).into(),
+    }
+}
+
+// turns the window `mapped` into a stack, built by `factory` if there is one.
+// Stacks are left alone, so `mapped` is a stack afterwards either way.
+fn stack_window(factory: Option<&StackFactory>, mapped: &mut CosmicMapped, output: &Output) {
+    if mapped.is_stack() {
+        return;
+    }
+    let Some(factory) = factory else {
+        mapped.convert_to_stack(std::iter::once((output, mapped.bbox())));
+        return;
+    };
+
+    let activated = mapped.is_activated(true);
+    let surfaces = mapped.windows().map(|(s, _)| s).collect();
+    let stack = CosmicMapped::from((factory.0)(surfaces, mapped.loop_handle()));
+    if let Some(geo) = *mapped.last_geometry.lock().unwrap() {
+        stack.set_geometry(geo);
+        *stack.last_geometry.lock().unwrap() = Some(geo);
+    }
+    *stack.tiling_node_id.lock().unwrap() = mapped.tiling_node_id.lock().unwrap().take();
+    stack.output_enter(output, stack.bbox());
+    stack.set_activated(activated);
+    stack.configure();
+    stack.refresh();
+    *mapped = stack;
+}
+
+type FocusObserverFn = dyn Fn(&KeyboardFocusTarget);
+
+#[derive(Clone)]
+pub struct FocusObserver(Arc<FocusObserverFn>);
+
+impl fmt::Debug for FocusObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FocusObserver").finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GapSpec {
+    Pixels(i32),
+    /// Fraction of the shorter side of the output
+    Fraction(f64),
+}
+
+impl GapSpec {
+    fn resolve(&self, output_size: Size<i32, Logical>) -> i32 {
+        match self {
+            GapSpec::Pixels(pixels) => *pixels,
+            GapSpec::Fraction(fraction) => {
+                (output_size.w.min(output_size.h) as f64 * fraction).round() as i32
+            }
+        }
+    }
+}
+
+impl From<u8> for GapSpec {
+    fn from(pixels: u8) -> Self {
+        GapSpec::Pixels(pixels as i32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AnimationCurve {
+    #[default]
+    EaseInOutCubic,
+    Linear,
+}
+
+impl AnimationCurve {
+    fn apply(&self, percentage: f32) -> f32 {
+        match self {
+            AnimationCurve::EaseInOutCubic => ease(EaseInOutCubic, 0.0, 1.0, percentage),
+            AnimationCurve::Linear => percentage.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TilingConfig {
+    pub gaps: (GapSpec, GapSpec),
+    pub animation_duration: Duration,
+    pub animation_curve: AnimationCurve,
+    /// Smallest size tiles can be resized to
+    pub min_size: Size<i32, Logical>,
+    /// Caps `min_size` to this fraction of the output size, so small outputs can still be resized
+    pub min_size_fraction: Option<f64>,
+    pub layout_mode: LayoutMode,
+    pub render_order: RenderOrder,
+    pub query_mode: QueryMode,
+    /// Centers a lone window on the output, capped to the given width
+    pub single_window_max_width: Option<i32>,
+    /// Biases new splits towards tiles of the given width / height ratio
+    pub preferred_tile_aspect: Option<f64>,
+    /// Lets windows moved onto a neighboring stack join it
+    pub stack_on_move: bool,
+    pub sizing_policy: SizingPolicy,
+    /// Windows of these app ids all join one stack per output
+    pub stack_by_app_id: HashSet<String>,
+    /// Distance the pointer may stray from a fork, before it stops being hit
+    pub fork_hysteresis: i32,
+    /// Gradually evens out groups, once one of their children gets too small
+    pub auto_balance: bool,
+    /// Eases the focus indicator in over the given duration, whenever focus moves to another node
+    pub focus_pulse_duration: Option<Duration>,
+    /// Waits for changes to settle this long before animating, animating only to the newest layout
+    pub animation_delay: Duration,
+    /// Share of a split given to the newly mapped window
+    pub new_window_fraction: f64,
+    /// Animates without waiting for the client any longer, once a blocker is pending this long
+    pub blocker_timeout: Duration,
+}
+
+impl Default for TilingConfig {
+    fn default() -> Self {
+        TilingConfig {
+            gaps: (GapSpec::Pixels(0), GapSpec::Pixels(4)),
+            animation_duration: ANIMATION_DURATION,
+            animation_curve: AnimationCurve::default(),
+            min_size: (360, 240).into(),
+            min_size_fraction: None,
+            layout_mode: LayoutMode::default(),
+            render_order: RenderOrder::default(),
+            query_mode: QueryMode::default(),
+            single_window_max_width: None,
+            preferred_tile_aspect: None,
+            stack_on_move: true,
+            sizing_policy: SizingPolicy::default(),
+            stack_by_app_id: HashSet::new(),
+            fork_hysteresis: 4,
+            auto_balance: false,
+            focus_pulse_duration: None,
+            animation_delay: Duration::ZERO,
+            new_window_fraction: 0.5,
+            blocker_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl TilingConfig {
+    fn min_length(&self, orientation: Orientation) -> i32 {
+        match orientation {
+            Orientation::Vertical => self.min_size.w,
+            Orientation::Horizontal => self.min_size.h,
+        }
+    }
+
+    fn min_length_on(&self, orientation: Orientation, output: &Output) -> i32 {
+        let min_length = self.min_length(orientation);
+        let Some(fraction) = self.min_size_fraction else { return min_length };
+        let output_length = match orientation {
+            Orientation::Vertical => output.geometry().size.w,
+            Orientation::Horizontal => output.geometry().size.h,
+        };
+        min_length.min((output_length as f64 * fraction).round() as i32)
+    }
+
+    fn min_size_on(&self, output: &Output) -> Size<i32, Logical> {
+        (
+            self.min_length_on(Orientation::Vertical, output),
+            self.min_length_on(Orientation::Horizontal, output),
+        )
+            .into()
+    }
+
+    fn min_node_length(
+        &self,
+        tree: &Tree<Data>,
+        node_id: &NodeId,
+        orientation: Orientation,
+    ) -> i32 {
+        let node = tree.get(node_id).unwrap();
+        match node.data() {
+            Data::Mapped { .. } => self.min_length(orientation),
+            Data::Group {
+                orientation: group_orientation,
+                ..
+            } => {
+                let children = node
+                    .children()
+                    .iter()
+                    .map(|child_id| self.min_node_length(tree, child_id, orientation));
+                if *group_orientation == orientation {
+                    children.sum()
+                } else {
+                    children.max().unwrap_or(0)
+                }
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct TilingLayout {
-    gaps: (i32, i32),
+    config: TilingConfig,
     queues: HashMap<OutputData, TreeQueue>,
-    standby_tree: Option<Tree<Data>>,
+    // keyed by output name
+    standby_trees: HashMap<String, Tree<Data>>,
     pending_blockers: Vec<TilingBlocker>,
+    geometry_hook: Option<GeometryHook>,
+    focus_observer: Option<FocusObserver>,
+    stack_factory: Option<StackFactory>,
+    resize_accel: HashMap<Seat<State>, ResizeAccel>,
+    // swallowing child -> swallowed parent
+    swallowed: HashMap<CosmicMapped, CosmicMapped>,
+    reservations: HashMap<ReservationToken, Reservation>,
+    next_reservation: u64,
+    // highlighted, but not yet focused group per seat
+    group_selection: HashMap<Seat<State>, (Direction, NodeId, Output)>,
+    changes: ChangeLog,
+    // windows ignored by `element_under`
+    input_passthrough: HashSet<CosmicMapped>,
+    // windows mirrored into the trees of all outputs besides their own
+    sticky_windows: HashSet<CosmicMapped>,
 }
 
 #[derive(Debug, Clone)]
@@ -154,12 +743,20 @@ pub enum Data {
     Group {
         orientation: Orientation,
         sizes: Vec<i32>,
+        /// children, whose size should be kept, when siblings are added or removed
+        locked: Vec<bool>,
         last_geometry: Rectangle<i32, Logical>,
         alive: Arc<()>,
+        /// inner gap of direct children, instead of the configured one
+        gap_override: Option<i32>,
     },
     Mapped {
         mapped: CosmicMapped,
         last_geometry: Rectangle<i32, Logical>,
+        /// always stays the last child of its group
+        sticky_end: bool,
+        /// rendered above the other tiled windows
+        always_on_top: bool,
     },
 }
 
@@ -174,8 +771,10 @@ impl Data {
                 };
                 2
             ],
+            locked: vec![false; 2],
             last_geometry: geo,
             alive: Arc::new(()),
+            gap_override: None,
         }
     }
 
@@ -202,10 +801,65 @@ impl Data {
         }
     }
 
-    fn add_window(&mut self, idx: usize) {
+    fn set_orientation(&mut self, new_orientation: Orientation, preserve_ratios: bool) {
+        match self {
+            Data::Group {
+                orientation,
+                sizes,
+                last_geometry,
+                ..
+            } => {
+                let previous_length = match orientation {
+                    Orientation::Horizontal => last_geometry.size.h,
+                    Orientation::Vertical => last_geometry.size.w,
+                };
+                let new_length = match new_orientation {
+                    Orientation::Horizontal => last_geometry.size.h,
+                    Orientation::Vertical => last_geometry.size.w,
+                };
+
+                if preserve_ratios {
+                    // hand out the rounding remainder by largest fraction instead of to the last window
+                    let total = sizes.iter().sum::<i32>().max(1);
+                    let exact = sizes
+                        .iter()
+                        .map(|len| *len as f64 / total as f64 * new_length as f64)
+                        .collect::<Vec<_>>();
+                    for (len, exact) in sizes.iter_mut().zip(exact.iter()) {
+                        *len = exact.floor() as i32;
+                    }
+                    let mut by_fraction = (0..sizes.len()).collect::<Vec<_>>();
+                    by_fraction
+                        .sort_by(|a, b| exact[*b].fract().partial_cmp(&exact[*a].fract()).unwrap());
+                    let missing = new_length - sizes.iter().sum::<i32>();
+                    for idx in by_fraction.into_iter().take(missing.max(0) as usize) {
+                        sizes[idx] += 1;
+                    }
+                } else {
+                    sizes.iter_mut().for_each(|len| {
+                        *len = (((*len as f64) / (previous_length as f64)) * (new_length as f64))
+                            .round() as i32;
+                    });
+                    let sum: i32 = sizes.iter().sum();
+                    if sum < new_length {
+                        *sizes.last_mut().unwrap() += new_length - sum;
+                    }
+                }
+
+                *orientation = new_orientation;
+            }
+            Data::Mapped { .. } => panic!("Setting orientation of a leaf?"),
+        }
+    }
+
+    // `weights` are the window counts of the existing children and the new one,
+    // sizing the new slot by area instead of length.
+    // `min_length` is the configured minimum along the group's orientation.
+    fn add_window(&mut self, idx: usize, weights: Option<(&[usize], usize)>, min_length: i32) {
         match self {
             Data::Group {
                 sizes,
+                locked,
                 last_geometry,
                 orientation,
                 ..
@@ -214,16 +868,48 @@ impl Data {
                     Orientation::Horizontal => last_geometry.size.h,
                     Orientation::Vertical => last_geometry.size.w,
                 };
-                let equal_sizing = last_length / (sizes.len() as i32 + 1); // new window size
-                let remainder = last_length - equal_sizing; // size for the rest of the windowns
 
-                for size in sizes.iter_mut() {
-                    *size = ((*size as f64 / last_length as f64) * remainder as f64).round() as i32;
+                // only take space from unlocked windows, as long as there is enough of it
+                let locked_size: i32 = sizes
+                    .iter()
+                    .zip(locked.iter())
+                    .filter(|(_, locked)| **locked)
+                    .map(|(size, _)| *size)
+                    .sum();
+                let unlocked = locked.iter().filter(|locked| !**locked).count() as i32;
+                let keep_locked =
+                    locked_size > 0 && (last_length - locked_size) / (unlocked + 1) >= min_length;
+                let (available, resized) = if keep_locked {
+                    (last_length - locked_size, unlocked + 1)
+                } else {
+                    (last_length, sizes.len() as i32 + 1)
+                };
+                let equal_sizing = match weights {
+                    Some((existing, new)) => {
+                        let total = existing
+                            .iter()
+                            .zip(locked.iter())
+                            .filter(|(_, locked)| !(keep_locked && **locked))
+                            .map(|(count, _)| *count)
+                            .sum::<usize>()
+                            + new;
+                        (available as f64 * new as f64 / total as f64).round() as i32
+                    }
+                    None => available / resized,
+                }; // new window size
+                let remainder = available - equal_sizing; // size for the rest of the windowns
+
+                for (size, locked) in sizes.iter_mut().zip(locked.iter()) {
+                    if !(keep_locked && *locked) {
+                        *size =
+                            ((*size as f64 / available as f64) * remainder as f64).round() as i32;
+                    }
                 }
                 let used_size: i32 = sizes.iter().sum();
                 let new_size = last_length - used_size;
 
                 sizes.insert(idx, new_size);
+                locked.insert(idx, false);
             }
             Data::Mapped { .. } => panic!("Adding window to leaf?"),
         }
@@ -231,8 +917,9 @@ impl Data {
 
     fn swap_windows(&mut self, i: usize, j: usize) {
         match self {
-            Data::Group { sizes, .. } => {
+            Data::Group { sizes, locked, .. } => {
                 sizes.swap(i, j);
+                locked.swap(i, j);
             }
             Data::Mapped { .. } => panic!("Swapping windows to a leaf?"),
         }
@@ -242,6 +929,7 @@ impl Data {
         match self {
             Data::Group {
                 sizes,
+                locked,
                 last_geometry,
                 orientation,
                 ..
@@ -251,16 +939,32 @@ impl Data {
                     Orientation::Vertical => last_geometry.size.w,
                 };
                 let old_size = sizes.remove(idx);
-                let remaining_size: i32 = sizes.iter().sum();
-
-                for size in sizes.iter_mut() {
-                    *size +=
-                        ((*size as f64 / remaining_size as f64) * old_size as f64).round() as i32;
+                locked.remove(idx);
+
+                // hand the space to unlocked windows, if there are any
+                let keep_locked =
+                    locked.iter().any(|locked| *locked) && locked.iter().any(|locked| !*locked);
+                let remaining_size: i32 = sizes
+                    .iter()
+                    .zip(locked.iter())
+                    .filter(|(_, locked)| !(keep_locked && **locked))
+                    .map(|(size, _)| *size)
+                    .sum();
+
+                for (size, locked) in sizes.iter_mut().zip(locked.iter()) {
+                    if !(keep_locked && *locked) {
+                        *size += ((*size as f64 / remaining_size as f64) * old_size as f64).round()
+                            as i32;
+                    }
                 }
                 let used_size: i32 = sizes.iter().sum();
                 let overflow = last_length - used_size;
                 if overflow != 0 {
-                    *sizes.last_mut().unwrap() += overflow;
+                    let last_unlocked = locked
+                        .iter()
+                        .rposition(|locked| !(keep_locked && *locked))
+                        .unwrap();
+                    sizes[last_unlocked] += overflow;
                 }
             }
             Data::Mapped { .. } => panic!("Added window to leaf?"),
@@ -295,8 +999,9 @@ impl Data {
                     *len = (((*len as f64) / (previous_length as f64)) * (new_length as f64))
                         .round() as i32;
                 });
+                // rounding may also overshoot, e.g. when the gaps grow
                 let sum: i32 = sizes.iter().sum();
-                if sum < new_length {
+                if sum != new_length {
                     *sizes.last_mut().unwrap() += new_length - sum;
                 }
                 *last_geometry = geo;
@@ -323,52 +1028,295 @@ enum FocusedNodeData {
 
 impl TilingLayout {
     pub fn new(gaps: (u8, u8)) -> TilingLayout {
+        TilingLayout::with_config(TilingConfig {
+            gaps: (gaps.0.into(), gaps.1.into()),
+            ..TilingConfig::default()
+        })
+    }
+
+    pub fn with_config(config: TilingConfig) -> TilingLayout {
         TilingLayout {
-            gaps: (gaps.0 as i32, gaps.1 as i32),
+            config,
             queues: HashMap::new(),
-            standby_tree: None,
+            standby_trees: HashMap::new(),
             pending_blockers: Vec::new(),
+            geometry_hook: None,
+            focus_observer: None,
+            stack_factory: None,
+            resize_accel: HashMap::new(),
+            swallowed: HashMap::new(),
+            reservations: HashMap::new(),
+            next_reservation: 0,
+            group_selection: HashMap::new(),
+            changes: ChangeLog::default(),
+            input_passthrough: HashSet::new(),
+            sticky_windows: HashSet::new(),
         }
     }
 
-    pub fn map_output(&mut self, output: &Output, location: Point<i32, Logical>) {
-        if !self.queues.contains_key(output) {
-            self.queues.insert(
-                OutputData {
-                    output: output.clone(),
-                    location,
-                },
-                TreeQueue {
-                    trees: {
-                        let mut queue = VecDeque::new();
-                        queue.push_back((
-                            self.standby_tree.take().unwrap_or_else(Tree::new),
-                            Duration::ZERO,
-                            None,
-                        ));
-                        queue
-                    },
-                    animation_start: None,
-                },
+    pub fn config(&self) -> &TilingConfig {
+        &self.config
+    }
+
+    pub fn set_gaps(&mut self, outer: GapSpec, inner: GapSpec) {
+        self.config.gaps = (outer, inner);
+        self.recalculate_all();
+    }
+
+    pub fn set_single_window_max_width(&mut self, max_width: Option<i32>) {
+        self.config.single_window_max_width = max_width;
+    }
+
+    pub fn set_layout_mode(&mut self, layout_mode: LayoutMode) {
+        self.config.layout_mode = layout_mode;
+        if layout_mode != LayoutMode::Quadrant {
+            return;
+        }
+
+        for (output_data, queue) in self.queues.iter_mut() {
+            let mut tree = queue.trees.back().unwrap().0.copy_clone();
+            TilingLayout::reflow_quadrants(
+                &mut tree,
+                &output_data.output,
+                self.stack_factory.as_ref(),
+                None,
             );
-        } else {
-            let tree = self.queues.remove(output).unwrap();
-            self.queues.insert(
-                OutputData {
-                    output: output.clone(),
-                    location,
-                },
-                tree,
+            let blocker = TilingLayout::update_positions(
+                &output_data.output,
+                &mut tree,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                queue.overscan,
+                queue.reserved_zone,
             );
+            queue.push_tree(tree, self.config.animation_duration, blocker);
         }
     }
 
-    pub fn unmap_output(
-        &mut self,
-        output: &Output,
-        toplevel_info: &mut ToplevelInfoState<State, CosmicSurface>,
-    ) {
-        if let Some(mut src) = self.queues.remove(output) {
+    pub fn set_preferred_tile_aspect(&mut self, aspect: Option<f64>) {
+        self.config.preferred_tile_aspect = aspect;
+    }
+
+    /// Forces the orientation of the split made for the next window mapped on `output`
+    pub fn set_next_split(&mut self, output: &Output, orientation: Option<Orientation>) {
+        if let Some(queue) = self.queues.get_mut(output) {
+            queue.next_split = orientation;
+        }
+    }
+
+    /// Makes the next window mapped on the active output split the focused node on the side of `direction`,
+    /// `None` cancels a previous preselection
+    pub fn preselect(&mut self, seat: &Seat<State>, direction: Option<Direction>) {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get_mut(&output) else { return };
+        queue.preselect = direction.and_then(|direction| {
+            let target = seat.get_keyboard().unwrap().current_focus()?;
+            let (node_id, _) = TilingLayout::currently_focused_node(
+                &queue.trees.back().unwrap().0,
+                &output,
+                target,
+            )?;
+            Some((node_id, direction))
+        });
+    }
+
+    /// Zooms the group overview of `output`. Pointer queries are transformed as well,
+    /// so this should be reset once the overview is left.
+    pub fn set_overview_transform(&mut self, output: &Output, transform: Option<ViewTransform>) {
+        if let Some(queue) = self.queues.get_mut(output) {
+            queue.overview_transform = transform;
+        }
+    }
+
+    /// Insets the tiling area of `output` by `(top, right, bottom, left)`,
+    /// in addition to any gaps and exclusive zones.
+    pub fn set_output_overscan(&mut self, output: &Output, inset: (i32, i32, i32, i32)) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        queue.overscan = inset;
+
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Keeps `fraction` of the tiling area along `edge` free of tiled windows, a `fraction` of zero removes the zone.
+    ///
+    /// Nothing is tiled into the zone itself, use `reserved_zone_geometry` to place something there.
+    pub fn set_reserved_zone(&mut self, output: &Output, edge: Direction, fraction: f64) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        queue.reserved_zone = (fraction > 0.).then_some((edge, fraction.min(0.9)));
+
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Global geometry of the reserved zone of `output`, if any
+    pub fn reserved_zone_geometry(&self, output: &Output) -> Option<Rectangle<i32, Logical>> {
+        let queue = self.queues.get(output)?;
+        let (edge, fraction) = queue.reserved_zone?;
+        let area = TilingLayout::usable_area(output, &self.config, queue.overscan);
+        let mut geo = TilingLayout::zone_geometry(area, edge, fraction);
+        geo.loc += output.geometry().loc;
+        Some(geo)
+    }
+
+    pub fn add_stack_app_id(&mut self, app_id: impl Into<String>) {
+        self.config.stack_by_app_id.insert(app_id.into());
+    }
+
+    pub fn remove_stack_app_id(&mut self, app_id: &str) {
+        self.config.stack_by_app_id.remove(app_id);
+    }
+
+    pub fn set_sizing_policy(&mut self, sizing_policy: SizingPolicy) {
+        self.config.sizing_policy = sizing_policy;
+    }
+
+    pub fn set_stack_on_move(&mut self, stack_on_move: bool) {
+        self.config.stack_on_move = stack_on_move;
+    }
+
+    pub fn set_animation_delay(&mut self, delay: Duration) {
+        self.config.animation_delay = delay;
+    }
+
+    pub fn set_focus_pulse_duration(&mut self, duration: Option<Duration>) {
+        self.config.focus_pulse_duration = duration.filter(|duration| !duration.is_zero());
+    }
+
+    pub fn set_blocker_timeout(&mut self, timeout: Duration) {
+        self.config.blocker_timeout = timeout;
+    }
+
+    pub fn set_new_window_fraction(&mut self, fraction: f64) {
+        self.config.new_window_fraction = fraction.clamp(0.05, 0.95);
+    }
+
+    pub fn set_min_size_fraction(&mut self, fraction: Option<f64>) {
+        self.config.min_size_fraction = fraction;
+    }
+
+    pub fn set_auto_balance(&mut self, auto_balance: bool) {
+        self.config.auto_balance = auto_balance;
+    }
+
+    pub fn set_fork_hysteresis(&mut self, fork_hysteresis: i32) {
+        self.config.fork_hysteresis = fork_hysteresis.max(0);
+    }
+
+    pub fn set_query_mode(&mut self, query_mode: QueryMode) {
+        self.config.query_mode = query_mode;
+    }
+
+    pub fn set_render_order(&mut self, render_order: RenderOrder) {
+        self.config.render_order = render_order;
+    }
+
+    /// Allows rules to adjust the geometry a window gets configured with,
+    /// without changing its slot in the tree.
+    pub fn set_geometry_hook<F>(&mut self, hook: Option<F>)
+    where
+        F: Fn(&CosmicMapped, Rectangle<i32, Logical>) -> Rectangle<i32, Logical> + 'static,
+    {
+        self.geometry_hook = hook.map(|hook| GeometryHook(Arc::new(hook)));
+    }
+
+    pub fn set_stack_factory<F>(&mut self, factory: Option<F>)
+    where
+        F: Fn(Vec<CosmicSurface>, LoopHandle<'static, crate::state::Data>) -> CosmicStack + 'static,
+    {
+        self.stack_factory = factory.map(|factory| StackFactory(Arc::new(factory)));
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.queues.keys().map(|output_data| &output_data.output)
+    }
+
+    /// Called with the new target, whenever a layout operation decides focus should move.
+    pub fn set_focus_observer<F>(&mut self, observer: Option<F>)
+    where
+        F: Fn(&KeyboardFocusTarget) + 'static,
+    {
+        self.focus_observer = observer.map(|observer| FocusObserver(Arc::new(observer)));
+    }
+
+    pub fn map_output(&mut self, output: &Output, location: Point<i32, Logical>) {
+        if !self.queues.contains_key(output) {
+            // other outputs keep their standby trees until they return themselves
+            let tree = self
+                .standby_trees
+                .remove(&output.name())
+                .unwrap_or_else(Tree::new);
+            // the restored windows are mapped again from the log's point of view
+            self.changes
+                .record(TilingLayout::tree_changes(output, &Tree::new(), &tree));
+            self.queues.insert(
+                OutputData {
+                    output: output.clone(),
+                    location,
+                    backdrop_id: Id::new(),
+                    drop_hint_id: Id::new(),
+                    preselect_id: Id::new(),
+                },
+                TreeQueue {
+                    trees: {
+                        let mut queue = VecDeque::new();
+                        queue.push_back((tree, Duration::ZERO, None));
+                        queue
+                    },
+                    animation_start: None,
+                    overscan: (0, 0, 0, 0),
+                    reserved_zone: None,
+                    overview_transform: None,
+                    next_split: None,
+                    focus_history: VecDeque::new(),
+                    focus_cursor: 0,
+                    last_fork_hit: Arc::new(Mutex::new(None)),
+                    focus_pulse: Arc::new(Mutex::new(None)),
+                    pending_since: None,
+                    preselect: None,
+                    group_maximize: None,
+                    template: None,
+                    change_log: Some((output.clone(), self.changes.clone())),
+                },
+            );
+            self.sync_sticky();
+        } else {
+            let (output_data, tree) = self.queues.remove_entry(output).unwrap();
+            self.queues.insert(
+                OutputData {
+                    output: output.clone(),
+                    location,
+                    backdrop_id: output_data.backdrop_id,
+                    drop_hint_id: output_data.drop_hint_id,
+                    preselect_id: output_data.preselect_id,
+                },
+                tree,
+            );
+        }
+    }
+
+    pub fn unmap_output(
+        &mut self,
+        output: &Output,
+        toplevel_info: &mut ToplevelInfoState<State, CosmicSurface>,
+    ) {
+        if let Some(mut src) = self.queues.remove(output) {
             // Operate on last pending tree & unblock queue
             for blocker in src
                 .trees
@@ -377,10 +1325,15 @@ impl TilingLayout {
             {
                 self.pending_blockers.push(blocker);
             }
-            let (src, _, _) = src.trees.pop_back().expect("No tree in queue");
+            let (mut src, _, _) = src.trees.pop_back().expect("No tree in queue");
+            // sticky windows don't migrate, they are already shown on the remaining outputs
+            let promoted = self.detach_sticky(output, &mut src, toplevel_info);
+            // the windows leave `output`, whether they end up on another output or in standby
+            self.changes
+                .record(TilingLayout::tree_changes(output, &src, &Tree::new()));
 
             let Some((new_output, dst_queue)) = self.queues.iter_mut().next() else {
-                self.standby_tree = Some(src);
+                self.standby_trees.insert(output.name(), src);
                 return;
             };
 
@@ -398,6 +1351,7 @@ impl TilingLayout {
                 if let Data::Mapped {
                     mapped,
                     last_geometry: _,
+                    ..
                 } = node.data()
                 {
                     for (toplevel, _) in mapped.windows() {
@@ -408,10 +1362,238 @@ impl TilingLayout {
                     mapped.output_enter(&new_output.output, mapped.bbox());
                 }
             }
-            TilingLayout::merge_trees(src, &mut dst, orientation);
+            TilingLayout::merge_trees(src, &mut dst, &new_output.output, orientation);
+
+            let blocker = TilingLayout::update_positions(
+                &new_output.output,
+                &mut dst,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                dst_queue.overscan,
+                dst_queue.reserved_zone,
+            );
+            dst_queue.push_tree(dst, self.config.animation_duration, blocker);
+
+            // former mirrors now size their window
+            for promoted_output in promoted {
+                self.recalculate(&promoted_output, false);
+            }
+        }
+    }
+
+    // Takes the sticky windows out of `src`, the tree of the unmapped `output`. Those tiled there
+    // hand their geometry over to a mirror, whose outputs are returned to be laid out again.
+    fn detach_sticky(
+        &self,
+        output: &Output,
+        src: &mut Tree<Data>,
+        toplevel_info: &mut ToplevelInfoState<State, CosmicSurface>,
+    ) -> Vec<Output> {
+        let mut promoted = Vec::new();
+        for window in &self.sticky_windows {
+            let Some(node_id) = TilingLayout::sticky_node(src, window) else { continue };
+            if !TilingLayout::is_sticky_mirror(src, window) {
+                let Some((new_output, mirror_id)) =
+                    self.queues.iter().find_map(|(output_data, queue)| {
+                        let tree = &queue.trees.back().unwrap().0;
+                        TilingLayout::sticky_node(tree, window)
+                            .map(|id| (output_data.output.clone(), id))
+                    })
+                else {
+                    continue;
+                };
+                *window.tiling_node_id.lock().unwrap() = Some(mirror_id);
+                for (toplevel, _) in window.windows() {
+                    toplevel_info.toplevel_leave_output(&toplevel, output);
+                    toplevel_info.toplevel_enter_output(&toplevel, &new_output);
+                }
+                window.set_bounds(new_output.geometry().size);
+                promoted.push(new_output);
+            }
+            TilingLayout::remove_from_tree(src, node_id);
+            window.output_leave(output);
+        }
+        TilingLayout::flatten(src);
+        promoted
+    }
+
+    /// Maps, relocates and unmaps outputs to match `desired` at once,
+    /// re-flowing each affected output only once.
+    pub fn reconfigure_outputs(
+        &mut self,
+        desired: Vec<(Output, Point<i32, Logical>)>,
+        toplevel_info: &mut ToplevelInfoState<State, CosmicSurface>,
+    ) {
+        let removed = self
+            .queues
+            .keys()
+            .filter(|output_data| {
+                !desired
+                    .iter()
+                    .any(|(output, _)| output == &output_data.output)
+            })
+            .map(|output_data| output_data.output.clone())
+            .collect::<Vec<_>>();
+
+        for (output, location) in &desired {
+            self.map_output(output, *location);
+        }
+
+        let mut src_trees = Vec::new();
+        for output in removed {
+            let mut src = self.queues.remove(&output).unwrap();
+            for blocker in src
+                .trees
+                .iter_mut()
+                .flat_map(|(_, _, blocker)| blocker.take())
+            {
+                self.pending_blockers.push(blocker);
+            }
+            let (src, _, _) = src.trees.pop_back().expect("No tree in queue");
+            src_trees.push((output, src));
+        }
+        if src_trees.is_empty() {
+            return;
+        }
+        // only once all removed outputs are gone, so mirrors on those aren't picked up
+        let mut promoted = Vec::new();
+        for (output, src) in src_trees.iter_mut() {
+            promoted.extend(self.detach_sticky(output, src, toplevel_info));
+            self.changes
+                .record(TilingLayout::tree_changes(output, src, &Tree::new()));
+        }
+
+        let Some((new_output, dst_queue)) = self.queues.iter_mut().next() else {
+            self.standby_trees.extend(
+                src_trees
+                    .into_iter()
+                    .map(|(output, tree)| (output.name(), tree)),
+            );
+            return;
+        };
+
+        let mut dst = dst_queue.trees.back().unwrap().0.copy_clone();
+        let orientation = match new_output.output.geometry().size {
+            x if x.w >= x.h => Orientation::Vertical,
+            _ => Orientation::Horizontal,
+        };
+        for (output, src) in src_trees {
+            for node in src
+                .root_node_id()
+                .and_then(|root_id| src.traverse_pre_order(root_id).ok())
+                .into_iter()
+                .flatten()
+            {
+                if let Data::Mapped { mapped, .. } = node.data() {
+                    for (toplevel, _) in mapped.windows() {
+                        toplevel_info.toplevel_leave_output(&toplevel, &output);
+                        toplevel_info.toplevel_enter_output(&toplevel, &new_output.output);
+                    }
+                    mapped.output_leave(&output);
+                    mapped.output_enter(&new_output.output, mapped.bbox());
+                }
+            }
+            TilingLayout::merge_trees(src, &mut dst, &new_output.output, orientation);
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &new_output.output,
+            &mut dst,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            dst_queue.overscan,
+            dst_queue.reserved_zone,
+        );
+        dst_queue.push_tree(dst, self.config.animation_duration, blocker);
+
+        // former mirrors now size their window
+        for promoted_output in promoted {
+            self.recalculate(&promoted_output, false);
+        }
+    }
+
+    pub fn distribute_across_outputs(
+        &mut self,
+        seat: &Seat<State>,
+        toplevel_info: &mut ToplevelInfoState<State, CosmicSurface>,
+    ) {
+        // start at the active output and go from left to right from there
+        let active_output = seat.active_output();
+        let mut outputs = self
+            .queues
+            .keys()
+            .map(|output_data| (output_data.output.clone(), output_data.location))
+            .collect::<Vec<_>>();
+        outputs
+            .sort_by_key(|(output, location)| (output != &active_output, location.x, location.y));
+        if outputs.is_empty() {
+            return;
+        }
+
+        let mut trees = HashMap::new();
+        let mut windows = Vec::new();
+        for (output, _) in &outputs {
+            let queue = self.queues.get(output).unwrap();
+            let mut tree = queue.trees.back().unwrap().0.copy_clone();
+            if let Some(root_id) = tree.root_node_id().cloned() {
+                windows.extend(
+                    tree.traverse_pre_order(&root_id)
+                        .unwrap()
+                        .filter_map(|node| match node.data() {
+                            Data::Mapped { mapped, .. } => Some((mapped.clone(), output.clone())),
+                            Data::Group { .. } => None,
+                        }),
+                );
+                tree.remove_node(root_id, RemoveBehavior::DropChildren)
+                    .unwrap();
+            }
+            trees.insert(output.clone(), tree);
+        }
+
+        for (i, (mapped, old_output)) in windows.into_iter().enumerate() {
+            let (output, _) = &outputs[i % outputs.len()];
+            if output != &old_output {
+                for (toplevel, _) in mapped.windows() {
+                    toplevel_info.toplevel_leave_output(&toplevel, &old_output);
+                    toplevel_info.toplevel_enter_output(&toplevel, output);
+                }
+                mapped.output_leave(&old_output);
+                mapped.output_enter(output, mapped.bbox());
+                mapped.set_bounds(output.geometry().size);
+            }
+
+            let tree = trees.get_mut(output).unwrap();
+            TilingLayout::map_to_tree(
+                tree,
+                mapped,
+                output,
+                None::<std::iter::Empty<&CosmicMapped>>,
+                None,
+                self.config.preferred_tile_aspect,
+                None,
+                self.config.new_window_fraction,
+            );
+        }
 
-            let blocker = TilingLayout::update_positions(output, &mut dst, self.gaps);
-            dst_queue.push_tree(dst, ANIMATION_DURATION, blocker);
+        for (output, mut tree) in trees {
+            if self.config.layout_mode == LayoutMode::Quadrant {
+                TilingLayout::reflow_quadrants(
+                    &mut tree,
+                    &output,
+                    self.stack_factory.as_ref(),
+                    None,
+                );
+            }
+            let queue = self.queues.get_mut(&output).unwrap();
+            let blocker = TilingLayout::update_positions(
+                &output,
+                &mut tree,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                queue.overscan,
+                queue.reserved_zone,
+            );
+            queue.push_tree(tree, self.config.animation_duration, blocker);
         }
     }
 
@@ -421,112 +1603,1803 @@ impl TilingLayout {
         seat: &Seat<State>,
         focus_stack: impl Iterator<Item = &'a CosmicMapped> + 'a,
         direction: Option<Direction>,
-    ) {
-        let output = seat.active_output();
+        reservation: Option<ReservationToken>,
+    ) -> CosmicMapped {
+        let reservation = reservation
+            .and_then(|token| self.reservations.remove(&token))
+            .filter(|reservation| self.queues.contains_key(&reservation.output))
+            // the layout mode might have changed since the reservation
+            .filter(|_| self.config.layout_mode == LayoutMode::Manual);
+        let output = reservation
+            .as_ref()
+            .map(|reservation| reservation.output.clone())
+            .unwrap_or_else(|| seat.active_output());
         window.output_enter(&output, window.bbox());
         window.set_bounds(output.geometry().size);
-        self.map_internal(window, &output, Some(focus_stack), direction);
+        match reservation {
+            Some(reservation) => {
+                self.map_reserved(window.clone(), reservation);
+                window
+            }
+            None => match self
+                .map_by_template(window, &output)
+                .or_else(|window| self.map_by_app_id(window, &output))
+            {
+                Ok(mapped) => mapped,
+                Err(window) => self.map_internal(window, &output, Some(focus_stack), direction),
+            },
+        }
     }
 
-    fn map_internal<'a>(
+    /// Routes windows mapped onto `output` within the next seconds into the slots of `template`
+    /// matching their app id. Slots only take up space once filled, so unfilled slots collapse.
+    pub fn apply_template(&mut self, output: &Output, template: LayoutTemplate) {
+        if let Some(queue) = self.queues.get_mut(output) {
+            queue.template = Some(PendingTemplate {
+                template,
+                filled: HashMap::new(),
+                deadline: Instant::now() + TEMPLATE_TIMEOUT,
+            });
+        }
+    }
+
+    // Places `window` into a free slot of the pending template of `output`
+    fn map_by_template(
         &mut self,
-        window: impl Into<CosmicMapped>,
+        window: CosmicMapped,
         output: &Output,
-        focus_stack: Option<impl Iterator<Item = &'a CosmicMapped> + 'a>,
-        direction: Option<Direction>,
-    ) {
-        let queue = self.queues.get_mut(output).expect("Output not mapped?");
+    ) -> Result<CosmicMapped, CosmicMapped> {
+        if !window.is_window() || self.is_mapped(&window) {
+            return Err(window);
+        }
+        let queue = self.queues.get_mut(output).unwrap();
+        let Some(mut pending) = queue.template.take() else { return Err(window) };
+        if Instant::now() > pending.deadline {
+            return Err(window);
+        }
+
         let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        // windows of filled slots might have been closed or moved away since,
+        // free their slots before looking for one
+        pending.filled.retain(|_, mapped| {
+            mapped
+                .tiling_node_id
+                .lock()
+                .unwrap()
+                .clone()
+                .and_then(|node_id| tree.get(&node_id).ok())
+                .map(|node| node.data().is_mapped(Some(mapped)))
+                .unwrap_or(false)
+        });
+
+        let app_id = window.active_window().app_id();
+        let slots = pending.template.slots();
+        let Some(slot) = slots
+            .iter()
+            .enumerate()
+            .position(|(idx, slot)| *slot == app_id && !pending.filled.contains_key(&idx))
+        else {
+            queue.template = Some(pending);
+            return Err(window);
+        };
+        let slot_count = slots.len();
 
-        TilingLayout::map_to_tree(&mut tree, window, output, focus_stack, direction);
+        TilingLayout::fill_template_slot(
+            &mut tree,
+            &pending,
+            slot,
+            window.clone(),
+            output,
+            self.config.sizing_policy,
+            self.config.min_size_on(output),
+        );
+        pending.filled.insert(slot, window.clone());
+        if pending.filled.len() < slot_count {
+            queue.template = Some(pending);
+        }
 
-        let blocker = TilingLayout::update_positions(output, &mut tree, self.gaps);
-        queue.push_tree(tree, ANIMATION_DURATION, blocker);
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+        Ok(window)
     }
 
-    fn map_to_tree<'a>(
-        mut tree: &mut Tree<Data>,
-        window: impl Into<CosmicMapped>,
+    // inserts `window` for `slot` next to the already filled slots, leaving their sizes alone
+    fn fill_template_slot(
+        tree: &mut Tree<Data>,
+        pending: &PendingTemplate,
+        slot: usize,
+        window: CosmicMapped,
         output: &Output,
-        focus_stack: Option<impl Iterator<Item = &'a CosmicMapped> + 'a>,
-        direction: Option<Direction>,
+        policy: SizingPolicy,
+        min_size: Size<i32, Logical>,
     ) {
-        let window = window.into();
         let new_window = Node::new(Data::Mapped {
             mapped: window.clone(),
             last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+            sticky_end: false,
+            always_on_top: false,
         });
+        let nodes_in = |slots: &Range<usize>| {
+            pending
+                .filled
+                .iter()
+                .filter(|(idx, _)| slots.contains(idx))
+                .filter_map(|(_, mapped)| mapped.tiling_node_id.lock().unwrap().clone())
+                .collect::<Vec<_>>()
+        };
 
-        let window_id = if let Some(direction) = direction {
-            if let Some(root_id) = tree.root_node_id().cloned() {
-                let orientation = match direction {
-                    Direction::Left | Direction::Right => Orientation::Vertical,
-                    Direction::Up | Direction::Down => Orientation::Horizontal,
-                };
+        // the innermost group of the template having filled slots besides ours
+        let target = pending.template.slot_path(slot).into_iter().rev().find_map(
+            |(orientation, children, idx)| {
+                let filled = children
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != idx)
+                    .filter_map(|(i, (ratio, slots))| {
+                        let nodes = nodes_in(slots);
+                        (!nodes.is_empty()).then_some((i, *ratio, nodes))
+                    })
+                    .collect::<Vec<_>>();
+                (!filled.is_empty()).then_some((orientation, children[idx].0, idx, filled))
+            },
+        );
+
+        let Some((orientation, ratio, idx, filled)) = target else {
+            // first window of the template, keep everything else next to it
+            let rest = std::mem::replace(tree, Tree::new());
+            let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+            *window.tiling_node_id.lock().unwrap() = Some(new_id);
+            let orientation = match output.geometry().size {
+                x if x.w >= x.h => Orientation::Vertical,
+                _ => Orientation::Horizontal,
+            };
+            TilingLayout::merge_trees(rest, tree, output, orientation);
+            return;
+        };
+        let before = filled.iter().filter(|(i, _, _)| *i < idx).count();
+
+        // several filled children share a group, unless the windows were moved since
+        let nodes = filled
+            .iter()
+            .flat_map(|(_, _, nodes)| nodes.iter().cloned())
+            .collect::<Vec<_>>();
+        let group_id = TilingLayout::common_ancestor(tree, &nodes)
+            .filter(|_| filled.len() > 1)
+            .filter(|group_id| {
+                let node = tree.get(group_id).unwrap();
+                node.data().is_group()
+                    && node.data().orientation() == orientation
+                    && node.children().len() == filled.len()
+            });
 
+        let new_id = match group_id {
+            Some(group_id) => {
+                let new_id = tree
+                    .insert(new_window, InsertBehavior::UnderNode(&group_id))
+                    .unwrap();
+                tree.make_nth_sibling(&new_id, before).unwrap();
+                TilingLayout::add_to_group(tree, &group_id, before, policy, min_size);
+                new_id
+            }
+            None => {
+                // split the nearest filled child
+                let (_, other_ratio, nodes) = if before > 0 {
+                    &filled[before - 1]
+                } else {
+                    &filled[0]
+                };
+                let anchor = TilingLayout::common_ancestor(tree, nodes).unwrap();
                 let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
-                TilingLayout::new_group(&mut tree, &root_id, &new_id, orientation).unwrap();
-                tree.make_nth_sibling(
+                let group_id =
+                    TilingLayout::new_group(tree, &anchor, &new_id, orientation).unwrap();
+                if before == 0 {
+                    tree.make_first_sibling(&new_id).unwrap();
+                }
+                TilingLayout::split_for_new_window(
+                    tree,
+                    &group_id,
                     &new_id,
-                    match direction {
-                        Direction::Left | Direction::Up => 1,
+                    ratio / (ratio + other_ratio),
+                );
+                new_id
+            }
+        };
+        *window.tiling_node_id.lock().unwrap() = Some(new_id);
+    }
+
+    // lowest node, that is one of `nodes` or an ancestor of all of them
+    fn common_ancestor(tree: &Tree<Data>, nodes: &[NodeId]) -> Option<NodeId> {
+        let (first, rest) = nodes.split_first()?;
+        let is_below = |node_id: &NodeId, ancestor: &NodeId| {
+            node_id == ancestor
+                || tree
+                    .ancestor_ids(node_id)
+                    .map(|mut ids| ids.any(|id| id == ancestor))
+                    .unwrap_or(false)
+        };
+        std::iter::once(first.clone())
+            .chain(tree.ancestor_ids(first).ok()?.cloned())
+            .find(|candidate| rest.iter().all(|node_id| is_below(node_id, candidate)))
+    }
+
+    // Adds `window` to the stack of its app id, if there is a rule for it, returning that stack
+    fn map_by_app_id(
+        &mut self,
+        window: CosmicMapped,
+        output: &Output,
+    ) -> Result<CosmicMapped, CosmicMapped> {
+        if !window.is_window() || self.is_mapped(&window) {
+            return Err(window);
+        }
+        let app_id = window.active_window().app_id();
+        if !self.config.stack_by_app_id.contains(&app_id) {
+            return Err(window);
+        }
+
+        let queue = self.queues.get_mut(output).unwrap();
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let Some(node_id) = tree.root_node_id().and_then(|root| {
+            tree.traverse_pre_order_ids(root).unwrap().find(|id| {
+                matches!(
+                    tree.get(id).unwrap().data(),
+                    Data::Mapped { mapped, .. } if mapped.windows().any(|(w, _)| w.app_id() == app_id)
+                )
+            })
+        }) else {
+            return Err(window);
+        };
+
+        let stack = match tree.get_mut(&node_id).unwrap().data_mut() {
+            Data::Mapped { mapped, .. } => {
+                if mapped.is_window() {
+                    stack_window(self.stack_factory.as_ref(), mapped, output);
+                }
+                mapped
+                    .stack_ref_mut()
+                    .unwrap()
+                    .add_window(window.active_window(), None);
+                mapped.clone()
+            }
+            _ => unreachable!(),
+        };
+
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+        Ok(stack)
+    }
+
+    /// Splits the whole tree in two, with `window` taking the `side` half
+    pub fn map_as_root_split(
+        &mut self,
+        window: CosmicMapped,
+        orientation: Orientation,
+        side: Side,
+        seat: &Seat<State>,
+    ) {
+        if self.is_mapped(&window) {
+            warn!(?window, "Window is already mapped, ignoring.");
+            return;
+        }
+
+        let output = seat.active_output();
+        window.output_enter(&output, window.bbox());
+        window.set_bounds(output.geometry().size);
+
+        let queue = self.queues.get_mut(&output).expect("Output not mapped?");
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let root_id = tree.root_node_id().cloned();
+        let new_id = tree
+            .insert(
+                Node::new(Data::Mapped {
+                    mapped: window.clone(),
+                    last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+                    sticky_end: false,
+                    always_on_top: false,
+                }),
+                InsertBehavior::AsRoot,
+            )
+            .unwrap();
+        if let Some(root_id) = root_id {
+            TilingLayout::new_group(&mut tree, &root_id, &new_id, orientation).unwrap();
+            if side == Side::First {
+                tree.make_first_sibling(&new_id).unwrap();
+            }
+        }
+        *window.tiling_node_id.lock().unwrap() = Some(new_id);
+
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Computes the slot a window would get when splitting the focused window towards `direction`.
+    /// The split itself happens once a window is mapped with the returned token.
+    ///
+    /// Like any window split off the focused one, the slot gets `new_window_fraction` of it,
+    /// regardless of the sizing policy. If the focused node is gone by then, the window is mapped
+    /// as usual and won't match the returned geometry.
+    ///
+    /// Returns `None` if the seat's output isn't part of this layout, or in the quadrant and
+    /// columns layout modes, which place new windows themselves.
+    pub fn reserve_split(
+        &mut self,
+        direction: Direction,
+        seat: &Seat<State>,
+    ) -> Option<(Rectangle<i32, Logical>, ReservationToken)> {
+        if self.config.layout_mode != LayoutMode::Manual {
+            return None;
+        }
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get(&output) else {
+            warn!(?output, "Output not mapped, can't reserve a split.");
+            return None;
+        };
+        let tree = &queue.trees.back().unwrap().0;
+        let output_size = output.geometry().size;
+        let inner = self.config.gaps.1.resolve(output_size);
+
+        let node = seat
+            .get_keyboard()
+            .unwrap()
+            .current_focus()
+            .and_then(|target| TilingLayout::currently_focused_node(tree, &output, target))
+            .map(|(id, _)| id)
+            .or_else(|| tree.root_node_id().cloned())
+            .map(|id| {
+                let reserved = match tree.get(&id).unwrap().data() {
+                    Data::Mapped { mapped, .. } => ReservedNode::Window(mapped.clone()),
+                    Data::Group { alive, .. } => ReservedNode::Group(Arc::downgrade(alive)),
+                };
+                (id, reserved)
+            });
+
+        let mut geo = match node.as_ref().map(|(id, _)| id) {
+            Some(node_id) => {
+                let data = tree.get(node_id).unwrap().data();
+                let mut slot = *data.geometry();
+                if data.is_mapped(None) {
+                    slot.loc -= (inner, inner).into();
+                    slot.size += (inner * 2, inner * 2).into();
+                }
+                let fraction = self.config.new_window_fraction;
+                match direction {
+                    Direction::Left | Direction::Right => {
+                        let new_length = TilingLayout::new_window_length(slot.size.w, fraction);
+                        if direction == Direction::Right {
+                            slot.loc.x += slot.size.w - new_length;
+                        }
+                        slot.size.w = new_length;
+                    }
+                    Direction::Up | Direction::Down => {
+                        let new_length = TilingLayout::new_window_length(slot.size.h, fraction);
+                        if direction == Direction::Down {
+                            slot.loc.y += slot.size.h - new_length;
+                        }
+                        slot.size.h = new_length;
+                    }
+                }
+                slot
+            }
+            None => TilingLayout::tiling_area(
+                &output,
+                &self.config,
+                queue.overscan,
+                queue.reserved_zone,
+            ),
+        };
+        geo.loc += (inner, inner).into();
+        geo.size -= (inner * 2, inner * 2).into();
+        geo.loc += output.geometry().loc;
+
+        let token = ReservationToken(self.next_reservation);
+        self.next_reservation += 1;
+        self.reservations.insert(
+            token,
+            Reservation {
+                output,
+                node,
+                direction,
+            },
+        );
+        Some((geo, token))
+    }
+
+    pub fn cancel_reservation(&mut self, token: ReservationToken) {
+        self.reservations.remove(&token);
+    }
+
+    fn map_reserved(&mut self, window: CosmicMapped, reservation: Reservation) {
+        if self.is_mapped(&window) {
+            warn!(?window, "Window is already mapped, ignoring.");
+            return;
+        }
+
+        let output = reservation.output;
+        let queue = self.queues.get_mut(&output).unwrap();
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let node_id = reservation.node.and_then(|(node_id, reserved)| {
+            let data = tree.get(&node_id).ok()?.data();
+            let unchanged = match (data, reserved) {
+                (Data::Mapped { mapped, .. }, ReservedNode::Window(window)) => mapped == &window,
+                (Data::Group { alive, .. }, ReservedNode::Group(group)) => {
+                    Arc::as_ptr(alive) == group.as_ptr()
+                }
+                _ => false,
+            };
+            unchanged.then_some(node_id)
+        });
+        match node_id {
+            Some(node_id) => {
+                let new_id = tree
+                    .insert(
+                        Node::new(Data::Mapped {
+                            mapped: window.clone(),
+                            last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+                            sticky_end: false,
+                            always_on_top: false,
+                        }),
+                        InsertBehavior::AsRoot,
+                    )
+                    .unwrap();
+                let orientation = match reservation.direction {
+                    Direction::Left | Direction::Right => Orientation::Vertical,
+                    Direction::Up | Direction::Down => Orientation::Horizontal,
+                };
+                let group_id =
+                    TilingLayout::new_group(&mut tree, &node_id, &new_id, orientation).unwrap();
+                if matches!(reservation.direction, Direction::Left | Direction::Up) {
+                    tree.make_first_sibling(&new_id).unwrap();
+                }
+                // the same split `reserve_split` computed the hint for
+                TilingLayout::split_for_new_window(
+                    &mut tree,
+                    &group_id,
+                    &new_id,
+                    self.config.new_window_fraction,
+                );
+                TilingLayout::keep_sticky_last(&mut tree);
+                *window.tiling_node_id.lock().unwrap() = Some(new_id);
+            }
+            // the reserved node went away or holds something else now, map like any other window
+            None => {
+                TilingLayout::map_to_tree(
+                    &mut tree,
+                    window,
+                    &output,
+                    None::<std::iter::Empty<&CosmicMapped>>,
+                    None,
+                    self.config.preferred_tile_aspect,
+                    None,
+                    self.config.new_window_fraction,
+                );
+            }
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    pub fn map_as_tab<'a>(
+        &mut self,
+        window: CosmicMapped,
+        seat: &Seat<State>,
+        focus_stack: impl Iterator<Item = &'a CosmicMapped> + 'a,
+    ) {
+        if self.is_mapped(&window) {
+            warn!(?window, "Window is already mapped, ignoring.");
+            return;
+        }
+
+        let output = seat.active_output();
+        window.output_enter(&output, window.bbox());
+        window.set_bounds(output.geometry().size);
+
+        let queue = self.queues.get_mut(&output).expect("Output not mapped?");
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        match TilingLayout::last_active_window(&tree, focus_stack) {
+            Some((node_id, _)) if window.is_window() => {
+                match tree.get_mut(&node_id).unwrap().data_mut() {
+                    Data::Mapped { mapped, .. } => {
+                        if mapped.is_window() {
+                            stack_window(self.stack_factory.as_ref(), mapped, &output);
+                        }
+                        mapped
+                            .stack_ref_mut()
+                            .unwrap()
+                            .add_window(window.active_window(), None);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            // nothing to stack onto, split instead
+            _ => {
+                TilingLayout::map_to_tree(
+                    &mut tree,
+                    window,
+                    &output,
+                    None::<std::iter::Empty<&CosmicMapped>>,
+                    None,
+                    self.config.preferred_tile_aspect,
+                    None,
+                    self.config.new_window_fraction,
+                );
+            }
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    fn map_internal<'a>(
+        &mut self,
+        window: impl Into<CosmicMapped>,
+        output: &Output,
+        focus_stack: Option<impl Iterator<Item = &'a CosmicMapped> + 'a>,
+        direction: Option<Direction>,
+    ) -> CosmicMapped {
+        let window = window.into();
+        if self.is_mapped(&window) {
+            warn!(?window, "Window is already mapped, ignoring.");
+            return window;
+        }
+
+        let queue = self.queues.get_mut(output).expect("Output not mapped?");
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let preselect = queue
+            .preselect
+            .take()
+            .filter(|(node_id, _)| tree.get(node_id).is_ok());
+        // the window might end up in the focused stack or a quadrant's stack
+        let mut holder = window.clone();
+        if let Some((node_id, direction)) = preselect {
+            TilingLayout::map_beside(&mut tree, window.clone(), &node_id, direction);
+        } else if self.config.layout_mode == LayoutMode::Columns {
+            TilingLayout::map_to_columns(
+                &mut tree,
+                window.clone(),
+                focus_stack,
+                direction,
+                self.config.sizing_policy,
+                self.config.min_size_on(output),
+            );
+        } else {
+            holder = TilingLayout::map_to_tree(
+                &mut tree,
+                window.clone(),
+                output,
+                focus_stack,
+                direction,
+                self.config.preferred_tile_aspect,
+                queue.next_split.take(),
+                self.config.new_window_fraction,
+            );
+        }
+        if self.config.layout_mode == LayoutMode::Quadrant {
+            if let Some(quadrant) = TilingLayout::reflow_quadrants(
+                &mut tree,
+                output,
+                self.stack_factory.as_ref(),
+                Some(&window),
+            ) {
+                holder = quadrant;
+            }
+        }
+
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+        holder
+    }
+
+    /// Returns and clears the changes recorded since the last call, oldest first.
+    ///
+    /// Only the most recent changes are kept, if this isn't called regularly.
+    pub fn drain_changes(&mut self) -> Vec<LayoutChange> {
+        self.changes.0.lock().unwrap().drain(..).collect()
+    }
+
+    // semantic difference between two trees of `output`
+    fn tree_changes(output: &Output, old: &Tree<Data>, new: &Tree<Data>) -> Vec<LayoutChange> {
+        let nodes = |tree: &Tree<Data>| {
+            tree.root_node_id()
+                .into_iter()
+                .flat_map(|root_id| tree.traverse_pre_order_ids(root_id).unwrap())
+                .map(|id| {
+                    let node = tree.get(&id).unwrap();
+                    (id, node.parent().cloned(), node.data().clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        let old_nodes = nodes(old);
+        let new_nodes = nodes(new);
+
+        let mut old_windows = HashMap::new();
+        let mut old_groups = HashMap::new();
+        for (id, parent, data) in &old_nodes {
+            match data {
+                Data::Mapped { mapped, .. } => {
+                    old_windows.insert(mapped.clone(), (id.clone(), parent.clone()));
+                }
+                Data::Group {
+                    orientation,
+                    sizes,
+                    alive,
+                    ..
+                } => {
+                    old_groups.insert(
+                        Arc::as_ptr(alive),
+                        (id.clone(), *orientation, sizes.clone()),
+                    );
+                }
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (id, parent, data) in new_nodes {
+            match data {
+                Data::Mapped { mapped, .. } => match old_windows.remove(&mapped) {
+                    None => changes.push(LayoutChange::WindowMapped {
+                        output: output.clone(),
+                        node: id,
+                        window: mapped,
+                    }),
+                    Some((old_id, old_parent)) => {
+                        if old_id != id || old_parent != parent {
+                            changes.push(LayoutChange::WindowMoved {
+                                output: output.clone(),
+                                node: id,
+                                window: mapped,
+                            });
+                        }
+                    }
+                },
+                Data::Group {
+                    orientation,
+                    sizes,
+                    alive,
+                    ..
+                } => match old_groups.remove(&Arc::as_ptr(&alive)) {
+                    None => changes.push(LayoutChange::GroupCreated {
+                        output: output.clone(),
+                        node: id,
+                    }),
+                    Some((_, old_orientation, old_sizes)) => {
+                        if old_orientation != orientation {
+                            changes.push(LayoutChange::OrientationChanged {
+                                output: output.clone(),
+                                node: id.clone(),
+                                orientation,
+                            });
+                        }
+                        if old_sizes != sizes {
+                            changes.push(LayoutChange::SizesChanged {
+                                output: output.clone(),
+                                node: id,
+                                sizes,
+                            });
+                        }
+                    }
+                },
+            }
+        }
+        // whatever is left was removed, reported in tree order
+        for (id, _, data) in old_nodes {
+            match data {
+                Data::Mapped { mapped, .. } if old_windows.contains_key(&mapped) => {
+                    changes.push(LayoutChange::WindowUnmapped {
+                        output: output.clone(),
+                        window: mapped,
+                    })
+                }
+                Data::Group { alive, .. } if old_groups.contains_key(&Arc::as_ptr(&alive)) => {
+                    changes.push(LayoutChange::GroupRemoved {
+                        output: output.clone(),
+                        node: id,
+                    })
+                }
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    // Returns the element holding `window` afterwards, the focused stack it joined or itself
+    fn map_to_tree<'a>(
+        mut tree: &mut Tree<Data>,
+        window: impl Into<CosmicMapped>,
+        output: &Output,
+        focus_stack: Option<impl Iterator<Item = &'a CosmicMapped> + 'a>,
+        direction: Option<Direction>,
+        preferred_tile_aspect: Option<f64>,
+        next_split: Option<Orientation>,
+        new_window_fraction: f64,
+    ) -> CosmicMapped {
+        let window = window.into();
+        let new_window = Node::new(Data::Mapped {
+            mapped: window.clone(),
+            last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+            sticky_end: false,
+            always_on_top: false,
+        });
+
+        let window_id = if let Some(direction) = direction {
+            if let Some(root_id) = tree.root_node_id().cloned() {
+                let orientation = match direction {
+                    Direction::Left | Direction::Right => Orientation::Vertical,
+                    Direction::Up | Direction::Down => Orientation::Horizontal,
+                };
+
+                let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+                let group_id =
+                    TilingLayout::new_group(&mut tree, &root_id, &new_id, orientation).unwrap();
+                tree.make_nth_sibling(
+                    &new_id,
+                    match direction {
+                        Direction::Left | Direction::Up => 1,
                         Direction::Right | Direction::Down => 0,
                     },
                 )
                 .unwrap();
+                TilingLayout::split_for_new_window(tree, &group_id, &new_id, new_window_fraction);
+                new_id
+            } else {
+                tree.insert(new_window, InsertBehavior::AsRoot).unwrap()
+            }
+        } else {
+            let last_active = focus_stack
+                .and_then(|focus_stack| TilingLayout::last_active_window(&mut tree, focus_stack));
+
+            if let Some((ref node_id, mut last_active_window)) = last_active {
+                if window.is_window() && last_active_window.is_stack() {
+                    let surface = window.active_window();
+                    last_active_window
+                        .stack_ref_mut()
+                        .unwrap()
+                        .add_window(surface, None);
+                    return last_active_window;
+                }
+
+                let orientation = next_split.unwrap_or_else(|| {
+                    split_orientation(
+                        tree.get(node_id).unwrap().data().geometry().size,
+                        preferred_tile_aspect,
+                    )
+                });
+                let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+                let group_id =
+                    TilingLayout::new_group(&mut tree, &node_id, &new_id, orientation).unwrap();
+                TilingLayout::split_for_new_window(tree, &group_id, &new_id, new_window_fraction);
+                new_id
+            } else {
+                // nothing? then we add to the root
+                if let Some(root_id) = tree.root_node_id().cloned() {
+                    let orientation = next_split.unwrap_or_else(|| {
+                        split_orientation(output.geometry().size, preferred_tile_aspect)
+                    });
+                    let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+                    let group_id =
+                        TilingLayout::new_group(&mut tree, &root_id, &new_id, orientation).unwrap();
+                    TilingLayout::split_for_new_window(
+                        tree,
+                        &group_id,
+                        &new_id,
+                        new_window_fraction,
+                    );
+                    new_id
+                } else {
+                    tree.insert(new_window, InsertBehavior::AsRoot).unwrap()
+                }
+            }
+        };
+
+        TilingLayout::keep_sticky_last(tree);
+        *window.tiling_node_id.lock().unwrap() = Some(window_id);
+        window
+    }
+
+    fn map_beside(
+        tree: &mut Tree<Data>,
+        window: CosmicMapped,
+        node_id: &NodeId,
+        direction: Direction,
+    ) {
+        let new_window = Node::new(Data::Mapped {
+            mapped: window.clone(),
+            last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+            sticky_end: false,
+            always_on_top: false,
+        });
+        let orientation = match direction {
+            Direction::Left | Direction::Right => Orientation::Vertical,
+            Direction::Up | Direction::Down => Orientation::Horizontal,
+        };
+
+        let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+        TilingLayout::new_group(tree, node_id, &new_id, orientation).unwrap();
+        if matches!(direction, Direction::Left | Direction::Up) {
+            tree.make_first_sibling(&new_id).unwrap();
+        }
+
+        TilingLayout::keep_sticky_last(tree);
+        *window.tiling_node_id.lock().unwrap() = Some(new_id);
+    }
+
+    // A vertically split root holds the columns, each column is either a window
+    // or a horizontally split group of rows.
+    fn map_to_columns<'a>(
+        tree: &mut Tree<Data>,
+        window: CosmicMapped,
+        focus_stack: Option<impl Iterator<Item = &'a CosmicMapped> + 'a>,
+        direction: Option<Direction>,
+        policy: SizingPolicy,
+        min_size: Size<i32, Logical>,
+    ) {
+        let new_window = Node::new(Data::Mapped {
+            mapped: window.clone(),
+            last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+            sticky_end: false,
+            always_on_top: false,
+        });
+
+        let Some(root_id) = tree.root_node_id().cloned() else {
+            let window_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+            *window.tiling_node_id.lock().unwrap() = Some(window_id);
+            return;
+        };
+
+        let root_is_columns = matches!(
+            tree.get(&root_id).unwrap().data(),
+            Data::Group {
+                orientation: Orientation::Vertical,
+                ..
+            }
+        );
+        let focused = focus_stack
+            .and_then(|focus_stack| TilingLayout::last_active_window(tree, focus_stack))
+            .map(|(id, _)| id);
+        // the child of `parent` that contains `node`
+        let child_of = |tree: &Tree<Data>, parent: &NodeId, node: &NodeId| {
+            std::iter::once(node)
+                .chain(tree.ancestor_ids(node).unwrap())
+                .find(|id| tree.get(id).unwrap().parent() == Some(parent))
+                .cloned()
+        };
+        let column = focused.as_ref().map(|focused| {
+            if root_is_columns {
+                child_of(tree, &root_id, focused).unwrap()
+            } else {
+                root_id.clone()
+            }
+        });
+
+        let window_id = match (direction, column, focused) {
+            (Some(direction @ (Direction::Up | Direction::Down)), Some(column), Some(focused)) => {
+                let is_rows = matches!(
+                    tree.get(&column).unwrap().data(),
+                    Data::Group {
+                        orientation: Orientation::Horizontal,
+                        ..
+                    }
+                );
+                if is_rows {
+                    let row = child_of(tree, &column, &focused).unwrap();
+                    let idx = tree
+                        .children_ids(&column)
+                        .unwrap()
+                        .position(|id| id == &row)
+                        .unwrap()
+                        + (direction == Direction::Down) as usize;
+                    let new_id = tree
+                        .insert(new_window, InsertBehavior::UnderNode(&column))
+                        .unwrap();
+                    tree.make_nth_sibling(&new_id, idx).unwrap();
+                    TilingLayout::add_to_group(tree, &column, idx, policy, min_size);
+                    new_id
+                } else {
+                    let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+                    TilingLayout::new_group(tree, &column, &new_id, Orientation::Horizontal)
+                        .unwrap();
+                    if direction == Direction::Up {
+                        tree.make_first_sibling(&new_id).unwrap();
+                    }
+                    new_id
+                }
+            }
+            (direction, column, _) => {
+                let left = direction == Some(Direction::Left);
+                if root_is_columns {
+                    let len = tree.get(&root_id).unwrap().data().len();
+                    let idx = column
+                        .map(|column| {
+                            tree.children_ids(&root_id)
+                                .unwrap()
+                                .position(|id| id == &column)
+                                .unwrap()
+                                + (!left) as usize
+                        })
+                        .unwrap_or(if left { 0 } else { len });
+                    let new_id = tree
+                        .insert(new_window, InsertBehavior::UnderNode(&root_id))
+                        .unwrap();
+                    tree.make_nth_sibling(&new_id, idx).unwrap();
+                    TilingLayout::add_to_group(tree, &root_id, idx, policy, min_size);
+                    new_id
+                } else {
+                    let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
+                    TilingLayout::new_group(tree, &root_id, &new_id, Orientation::Vertical)
+                        .unwrap();
+                    if left {
+                        tree.make_first_sibling(&new_id).unwrap();
+                    }
+                    new_id
+                }
+            }
+        };
+
+        TilingLayout::keep_sticky_last(tree);
+        *window.tiling_node_id.lock().unwrap() = Some(window_id);
+    }
+
+    /// Returns what should receive focus once `window` is unmapped,
+    /// preferring its siblings over windows further up the tree.
+    pub fn neighbor_for_close(&self, window: &CosmicMapped) -> Option<KeyboardFocusTarget> {
+        let node_id = window.tiling_node_id.lock().unwrap().clone()?;
+        let tree = self.queues.values().find_map(|queue| {
+            let tree = &queue.trees.back().unwrap().0;
+            tree.get(&node_id)
+                .ok()
+                .filter(|node| node.data().is_mapped(Some(window)))
+                .map(|_| tree)
+        })?;
+
+        let mut child_id = node_id;
+        while let Some(parent_id) = tree.get(&child_id).unwrap().parent() {
+            let children = tree
+                .children_ids(parent_id)
+                .unwrap()
+                .cloned()
+                .collect::<Vec<_>>();
+            let idx = children.iter().position(|id| id == &child_id).unwrap();
+
+            // previous sibling first, its closest window is the last one
+            let candidate = idx
+                .checked_sub(1)
+                .map(|prev| (&children[prev], true))
+                .or_else(|| children.get(idx + 1).map(|next| (next, false)));
+            if let Some((sibling_id, before)) = candidate {
+                let mut windows = tree
+                    .traverse_pre_order(sibling_id)
+                    .unwrap()
+                    .filter_map(|node| match node.data() {
+                        Data::Mapped { mapped, .. } => Some(mapped.clone()),
+                        Data::Group { .. } => None,
+                    });
+                let neighbor = if before {
+                    windows.last()
+                } else {
+                    windows.next()
+                };
+                if let Some(neighbor) = neighbor {
+                    return Some(neighbor.into());
+                }
+            }
+
+            child_id = parent_id.clone();
+        }
+
+        None
+    }
+
+    /// Gives the focused node nearly all the space of its parent group, shrinking its siblings to slivers.
+    /// Calling it again restores the previous sizes of the group.
+    pub fn toggle_group_maximize(&mut self, seat: &Seat<State>) {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get_mut(&output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let focused = seat
+            .get_keyboard()
+            .unwrap()
+            .current_focus()
+            .and_then(|target| TilingLayout::currently_focused_node(&tree, &output, target))
+            .map(|(id, _)| id);
+        let group_id = focused
+            .as_ref()
+            .and_then(|id| tree.get(id).unwrap().parent().cloned());
+
+        let restored = queue.group_maximize.take().map(|(maximized_id, saved)| {
+            if let Ok(Data::Group { sizes, .. }) =
+                tree.get_mut(&maximized_id).map(|node| node.data_mut())
+            {
+                // the group might have been resized in the meantime
+                if sizes.len() == saved.len() {
+                    let length = sizes.iter().sum::<i32>();
+                    let saved_length = saved.iter().sum::<i32>();
+                    for (size, saved) in sizes.iter_mut().zip(saved.iter()) {
+                        *size =
+                            (*saved as f64 / saved_length as f64 * length as f64).round() as i32;
+                    }
+                    let sum = sizes.iter().sum::<i32>();
+                    *sizes.last_mut().unwrap() += length - sum;
+                }
+            }
+            maximized_id
+        });
+
+        if let (Some(focused_id), Some(group_id)) = (focused, group_id) {
+            if restored.as_ref() != Some(&group_id) {
+                let idx = tree
+                    .children_ids(&group_id)
+                    .unwrap()
+                    .position(|id| id == &focused_id)
+                    .unwrap();
+                if let Data::Group { sizes, .. } = tree.get_mut(&group_id).unwrap().data_mut() {
+                    queue.group_maximize = Some((group_id.clone(), sizes.clone()));
+                    let length = sizes.iter().sum::<i32>();
+                    let sliver = GROUP_MAXIMIZE_SLIVER.min(length / sizes.len() as i32);
+                    sizes.iter_mut().for_each(|size| *size = sliver);
+                    sizes[idx] = length - sliver * (sizes.len() as i32 - 1);
+                }
+            }
+        }
+
+        if restored.is_none() && queue.group_maximize.is_none() {
+            return;
+        }
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Splits the group made up of exactly `a` and `b`, giving `ratio` of its length to `a`
+    pub fn set_pair_ratio(
+        &mut self,
+        a: &CosmicMapped,
+        b: &CosmicMapped,
+        ratio: f64,
+    ) -> Result<(), NotAPair> {
+        let a_id = a.tiling_node_id.lock().unwrap().clone().ok_or(NotAPair)?;
+        let b_id = b.tiling_node_id.lock().unwrap().clone().ok_or(NotAPair)?;
+        let (output_data, queue) = self
+            .queues
+            .iter_mut()
+            .find(|(_, queue)| {
+                let tree = &queue.trees.back().unwrap().0;
+                tree.get(&a_id)
+                    .map(|node| node.data().is_mapped(Some(a)))
+                    .unwrap_or(false)
+                    && tree
+                        .get(&b_id)
+                        .map(|node| node.data().is_mapped(Some(b)))
+                        .unwrap_or(false)
+            })
+            .ok_or(NotAPair)?;
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let group_id = tree.get(&a_id).unwrap().parent().cloned().ok_or(NotAPair)?;
+        if tree.get(&b_id).unwrap().parent() != Some(&group_id)
+            || tree.children_ids(&group_id).unwrap().count() != 2
+        {
+            return Err(NotAPair);
+        }
+        let a_idx = tree
+            .children_ids(&group_id)
+            .unwrap()
+            .position(|id| id == &a_id)
+            .unwrap();
+
+        let orientation = tree.get(&group_id).unwrap().data().orientation();
+        let min_length = self.config.min_length_on(orientation, &output_data.output);
+        match tree.get_mut(&group_id).unwrap().data_mut() {
+            Data::Group { sizes, .. } => {
+                let length = sizes.iter().sum::<i32>();
+                let mut a_length = (length as f64 * ratio.clamp(0., 1.)).round() as i32;
+                if length >= min_length * 2 {
+                    a_length = a_length.clamp(min_length, length - min_length);
+                }
+                sizes[a_idx] = a_length;
+                sizes[1 - a_idx] = length - a_length;
+            }
+            Data::Mapped { .. } => unreachable!(),
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &output_data.output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+        Ok(())
+    }
+
+    /// Lets pointer input pass through `window`, as if it wasn't there
+    pub fn set_input_passthrough(&mut self, window: &CosmicMapped, passthrough: bool) {
+        if passthrough {
+            self.input_passthrough.insert(window.clone());
+        } else {
+            self.input_passthrough.remove(window);
+        }
+    }
+
+    pub fn set_always_on_top(&mut self, window: &CosmicMapped, on_top: bool) {
+        let Some(node_id) = window.tiling_node_id.lock().unwrap().clone() else { return };
+        let Some((output_data, queue)) = self.queues.iter_mut().find(|(_, queue)| {
+            queue
+                .trees
+                .back()
+                .unwrap()
+                .0
+                .get(&node_id)
+                .map(|node| node.data().is_mapped(Some(window)))
+                .unwrap_or(false)
+        }) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        if let Data::Mapped { always_on_top, .. } = tree.get_mut(&node_id).unwrap().data_mut() {
+            *always_on_top = on_top;
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &output_data.output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Shows `window` on every output, by mirroring it into the trees of all other outputs.
+    ///
+    /// The window keeps being sized by the output it is tiled on, the mirrors get the same share
+    /// of their group and show it cropped to their slot. Outputs mapped later get a mirror as well,
+    /// unmapped outputs take theirs with them.
+    pub fn set_sticky(&mut self, window: &CosmicMapped, sticky: bool) {
+        if sticky {
+            if self.is_mapped(window) && self.sticky_windows.insert(window.clone()) {
+                self.sync_sticky();
+            }
+        } else if self.sticky_windows.remove(window) {
+            self.remove_sticky_mirrors(window);
+        }
+    }
+
+    // first node of `tree` holding `window`
+    fn sticky_node(tree: &Tree<Data>, window: &CosmicMapped) -> Option<NodeId> {
+        let root_id = tree.root_node_id()?;
+        tree.traverse_pre_order_ids(root_id)
+            .unwrap()
+            .find(|id| tree.get(id).unwrap().data().is_mapped(Some(window)))
+    }
+
+    // whether `window` in `tree` only mirrors a sticky window, whose own node is in another tree
+    fn is_sticky_mirror(tree: &Tree<Data>, window: &CosmicMapped) -> bool {
+        window
+            .tiling_node_id
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|node_id| matches!(tree.get(node_id), Err(NodeIdError::InvalidNodeIdForTree)))
+            .unwrap_or(false)
+    }
+
+    // Gives every output exactly one node per sticky window, the mirrors taking the same share
+    // of their group as the window's own node. Called on every refresh, like `auto_balance`.
+    fn sync_sticky(&mut self) {
+        for window in self.sticky_windows.iter().cloned().collect::<Vec<_>>() {
+            let Some(home_id) = window.tiling_node_id.lock().unwrap().clone() else { continue };
+            let Some((home_output, home_tree)) = self.queues.iter().find_map(|(output_data, queue)| {
+                let tree = &queue.trees.back().unwrap().0;
+                tree.get(&home_id)
+                    .map(|node| node.data().is_mapped(Some(&window)))
+                    .unwrap_or(false)
+                    .then(|| (output_data.output.clone(), tree))
+            }) else {
+                continue;
+            };
+            let share = home_tree.get(&home_id).unwrap().parent().map(|parent_id| {
+                let parent = home_tree.get(parent_id).unwrap();
+                let idx = parent
+                    .children()
+                    .iter()
+                    .position(|id| id == &home_id)
+                    .unwrap();
+                match parent.data() {
+                    Data::Group {
+                        orientation, sizes, ..
+                    } => (
+                        *orientation,
+                        sizes[idx] as f64 / sizes.iter().sum::<i32>().max(1) as f64,
+                    ),
+                    Data::Mapped { .. } => unreachable!(),
+                }
+            });
+
+            for (output_data, queue) in self.queues.iter_mut() {
+                if queue.animation_start.is_some() || queue.trees.len() > 1 {
+                    continue;
+                }
+                let output = &output_data.output;
+                let mut tree = queue.trees.back().unwrap().0.copy_clone();
+                let mut mirrors = tree
+                    .root_node_id()
+                    .into_iter()
+                    .flat_map(|root_id| tree.traverse_pre_order_ids(root_id).unwrap())
+                    .filter(|id| {
+                        id != &home_id && tree.get(id).unwrap().data().is_mapped(Some(&window))
+                    })
+                    .collect::<Vec<_>>();
+                // the window was moved onto an output that mirrored it, the mirror has to go
+                let keep =
+                    (output != &home_output && !mirrors.is_empty()).then(|| mirrors.remove(0));
+                let mut changed = !mirrors.is_empty();
+                for mirror_id in mirrors {
+                    TilingLayout::remove_from_tree(&mut tree, mirror_id);
+                }
+
+                match keep {
+                    Some(mirror_id) => {
+                        let parent_id = tree.get(&mirror_id).unwrap().parent().cloned();
+                        if let (Some(parent_id), Some((orientation, fraction))) = (parent_id, share)
+                        {
+                            if tree.get(&parent_id).unwrap().data().orientation() == orientation {
+                                changed |= TilingLayout::set_child_share(
+                                    &mut tree, &parent_id, &mirror_id, fraction,
+                                );
+                            }
+                        }
+                    }
+                    None if output != &home_output => {
+                        let new_id = tree
+                            .insert(
+                                Node::new(Data::Mapped {
+                                    mapped: window.clone(),
+                                    last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+                                    sticky_end: false,
+                                    always_on_top: false,
+                                }),
+                                InsertBehavior::AsRoot,
+                            )
+                            .unwrap();
+                        if let Some(root_id) = tree
+                            .root_node_id()
+                            .cloned()
+                            .filter(|root_id| root_id != &new_id)
+                        {
+                            let (orientation, fraction) = share.unwrap_or_else(|| {
+                                (
+                                    split_orientation(
+                                        output.geometry().size,
+                                        self.config.preferred_tile_aspect,
+                                    ),
+                                    self.config.new_window_fraction,
+                                )
+                            });
+                            let group_id =
+                                TilingLayout::new_group(&mut tree, &root_id, &new_id, orientation)
+                                    .unwrap();
+                            TilingLayout::split_for_new_window(
+                                &mut tree, &group_id, &new_id, fraction,
+                            );
+                        }
+                        window.output_enter(output, window.bbox());
+                        changed = true;
+                    }
+                    None => {}
+                }
+
+                if changed {
+                    let blocker = TilingLayout::update_positions(
+                        output,
+                        &mut tree,
+                        &self.config,
+                        self.geometry_hook.as_ref(),
+                        queue.overscan,
+                        queue.reserved_zone,
+                    );
+                    queue.push_tree(tree, self.config.animation_duration, blocker);
+                }
+            }
+        }
+    }
+
+    // resizes `child_id` to `fraction` of `group_id`, scaling its siblings to fit.
+    // Returns whether the sizes changed noticeably.
+    fn set_child_share(
+        tree: &mut Tree<Data>,
+        group_id: &NodeId,
+        child_id: &NodeId,
+        fraction: f64,
+    ) -> bool {
+        let idx = tree
+            .children_ids(group_id)
+            .unwrap()
+            .position(|id| id == child_id)
+            .unwrap();
+        let Data::Group { sizes, .. } = tree.get_mut(group_id).unwrap().data_mut() else {
+            return false;
+        };
+        let total = sizes.iter().sum::<i32>();
+        let new_length = TilingLayout::new_window_length(total, fraction);
+        if (new_length - sizes[idx]).abs() <= 1 {
+            return false;
+        }
+
+        let old_rest = (total - sizes[idx]).max(1) as f64;
+        let new_rest = (total - new_length) as f64;
+        for (i, size) in sizes.iter_mut().enumerate() {
+            if i != idx {
+                *size = (*size as f64 * new_rest / old_rest).round() as i32;
+            }
+        }
+        sizes[idx] = new_length;
+        // rounding leftovers go to the last sibling
+        let last = if idx == sizes.len() - 1 {
+            idx - 1
+        } else {
+            sizes.len() - 1
+        };
+        sizes[last] += total - sizes.iter().sum::<i32>();
+        true
+    }
+
+    fn remove_sticky_mirrors(&mut self, window: &CosmicMapped) {
+        for (output_data, queue) in self.queues.iter_mut() {
+            let tree = &queue.trees.back().unwrap().0;
+            if !TilingLayout::is_sticky_mirror(tree, window) {
+                continue;
+            }
+            let Some(node_id) = TilingLayout::sticky_node(tree, window) else { continue };
+            let mut tree = tree.copy_clone();
+            TilingLayout::remove_from_tree(&mut tree, node_id);
+            window.output_leave(&output_data.output);
+
+            TilingLayout::flatten(&mut tree);
+            let blocker = TilingLayout::update_positions(
+                &output_data.output,
+                &mut tree,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                queue.overscan,
+                queue.reserved_zone,
+            );
+            queue.push_tree(tree, self.config.animation_duration, blocker);
+        }
+    }
+
+    pub fn set_sticky_end(&mut self, window: &CosmicMapped, sticky: bool) {
+        let Some(node_id) = window.tiling_node_id.lock().unwrap().clone() else { return };
+        let Some((output_data, queue)) = self.queues.iter_mut().find(|(_, queue)| {
+            queue
+                .trees
+                .back()
+                .unwrap()
+                .0
+                .get(&node_id)
+                .map(|node| node.data().is_mapped(Some(window)))
+                .unwrap_or(false)
+        }) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        if let Data::Mapped { sticky_end, .. } = tree.get_mut(&node_id).unwrap().data_mut() {
+            *sticky_end = sticky;
+        }
+        TilingLayout::keep_sticky_last(&mut tree);
+
+        let blocker = TilingLayout::update_positions(
+            &output_data.output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Checks the structural invariants of every committed tree,
+    /// returning a description of the first violation.
+    #[cfg(any(test, feature = "debug"))]
+    pub fn validate(&self) -> Result<(), String> {
+        for (output_data, queue) in &self.queues {
+            let output = output_data.output.name();
+            let tree = &queue.trees.back().unwrap().0;
+            let Some(root_id) = tree.root_node_id() else { continue };
+
+            for node_id in tree.traverse_pre_order_ids(root_id).unwrap() {
+                let node = tree.get(&node_id).unwrap();
+                let children = node.children().len();
+                match node.data() {
+                    Data::Group { sizes, locked, .. } => {
+                        if children < 2 {
+                            return Err(format!(
+                                "{}: group {:?} has {} children",
+                                output, node_id, children
+                            ));
+                        }
+                        if sizes.len() != children || locked.len() != children {
+                            return Err(format!(
+                                "{}: group {:?} has {} children, but {} sizes and {} locks",
+                                output,
+                                node_id,
+                                children,
+                                sizes.len(),
+                                locked.len()
+                            ));
+                        }
+                    }
+                    Data::Mapped { mapped, .. } => {
+                        if children != 0 {
+                            return Err(format!(
+                                "{}: window {:?} has {} children",
+                                output, node_id, children
+                            ));
+                        }
+                        let tiling_node_id = mapped.tiling_node_id.lock().unwrap().clone();
+                        let mirror = self.sticky_windows.contains(mapped)
+                            && TilingLayout::is_sticky_mirror(tree, mapped);
+                        if !mirror && tiling_node_id.as_ref() != Some(&node_id) {
+                            return Err(format!(
+                                "{}: window {:?} believes to be at {:?}",
+                                output, node_id, tiling_node_id
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_mapped(&self, window: &CosmicMapped) -> bool {
+        let Some(node_id) = window.tiling_node_id.lock().unwrap().clone() else { return false };
+        self.queues.values().any(|queue| {
+            queue
+                .trees
+                .back()
+                .unwrap()
+                .0
+                .get(&node_id)
+                .map(|node| node.data().is_mapped(Some(window)))
+                .unwrap_or(false)
+        })
+    }
+
+    fn record_current_focus(&mut self, seat: &Seat<State>) {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get(&output) else { return };
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return };
+        if let Some((_, FocusedNodeData::Window(mapped))) =
+            TilingLayout::currently_focused_node(&queue.trees.back().unwrap().0, &output, target)
+        {
+            self.record_focus(&output, &mapped);
+        }
+    }
+
+    fn record_focus(&mut self, output: &Output, mapped: &CosmicMapped) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        // navigating the history re-focuses the entry under the cursor
+        if queue.focus_history.get(queue.focus_cursor) == Some(mapped) {
+            return;
+        }
+        queue.focus_history.drain(..queue.focus_cursor);
+        queue.focus_history.retain(|m| m != mapped && m.alive());
+        queue.focus_history.push_front(mapped.clone());
+        queue.focus_history.truncate(FOCUS_HISTORY_LEN);
+        queue.focus_cursor = 0;
+    }
+
+    fn step_focus_history(&mut self, output: &Output, back: bool) -> FocusResult {
+        let Some(queue) = self.queues.get_mut(output) else { return FocusResult::None };
+        let tree = &queue.trees.back().unwrap().0;
+        let usable = |mapped: &CosmicMapped| {
+            mapped.alive()
+                && mapped
+                    .tiling_node_id
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|id| tree.get(id).ok())
+                    .map(|node| node.data().is_mapped(Some(mapped)))
+                    .unwrap_or(false)
+        };
+
+        let found = if back {
+            (queue.focus_cursor + 1..queue.focus_history.len())
+                .find(|idx| usable(&queue.focus_history[*idx]))
+        } else {
+            (0..queue.focus_cursor.min(queue.focus_history.len()))
+                .rev()
+                .find(|idx| usable(&queue.focus_history[*idx]))
+        };
+
+        match found {
+            Some(idx) => {
+                queue.focus_cursor = idx;
+                FocusResult::Some(queue.focus_history[idx].clone().into())
+            }
+            None => FocusResult::None,
+        }
+    }
+
+    pub fn focus_previous(&mut self, output: &Output) -> FocusResult {
+        self.step_focus_history(output, true)
+    }
+
+    pub fn focus_next(&mut self, output: &Output) -> FocusResult {
+        self.step_focus_history(output, false)
+    }
+
+    /// Keeps the size of `window` constant, when its siblings are added or removed.
+    pub fn set_size_locked(&mut self, window: &CosmicMapped, size_locked: bool) {
+        let Some(node_id) = window.tiling_node_id.lock().unwrap().clone() else { return };
+        for queue in self.queues.values_mut() {
+            let tree = &mut queue.trees.back_mut().unwrap().0;
+            let Some(parent_id) = tree
+                .get(&node_id)
+                .ok()
+                .filter(|node| node.data().is_mapped(Some(window)))
+                .and_then(|node| node.parent().cloned()) else { continue };
+            let idx = tree
+                .children_ids(&parent_id)
+                .unwrap()
+                .position(|id| id == &node_id)
+                .unwrap();
+            if let Data::Group { locked, .. } = tree.get_mut(&parent_id).unwrap().data_mut() {
+                locked[idx] = size_locked;
+            }
+            return;
+        }
+    }
+
+    /// Replaces `parent` with `child` in the same slot, until `child` is unmapped again.
+    pub fn swallow(&mut self, parent: &CosmicMapped, child: CosmicMapped) {
+        if self.replace_window(parent, child.clone()).is_some() {
+            self.swallowed.insert(child, parent.clone());
+        }
+    }
+
+    pub fn unswallow(&mut self, child: &CosmicMapped) -> Option<Output> {
+        let parent = self.swallowed.remove(child)?;
+        if !parent.alive() {
+            return None;
+        }
+        self.replace_window(child, parent)
+    }
+
+    fn replace_window(&mut self, old: &CosmicMapped, new: CosmicMapped) -> Option<Output> {
+        let node_id = old.tiling_node_id.lock().unwrap().clone()?;
+        let (output_data, queue) = self.queues.iter_mut().find(|(_, queue)| {
+            queue
+                .trees
+                .back()
+                .unwrap()
+                .0
+                .get(&node_id)
+                .map(|node| node.data().is_mapped(Some(old)))
+                .unwrap_or(false)
+        })?;
+        let output = output_data.output.clone();
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let data = tree.get_mut(&node_id).unwrap().data_mut();
+        let last_geometry = *data.geometry();
+        *data = Data::Mapped {
+            mapped: new.clone(),
+            last_geometry,
+            sticky_end: false,
+            always_on_top: false,
+        };
+
+        old.output_leave(&output);
+        old.set_tiled(false);
+        *old.tiling_node_id.lock().unwrap() = None;
+        new.output_enter(&output, new.bbox());
+        new.set_bounds(output.geometry().size);
+        *new.tiling_node_id.lock().unwrap() = Some(node_id);
+
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+
+        Some(output)
+    }
+
+    /// Unmaps `window`, remembering its slot for `retile`
+    pub fn float_out(&mut self, window: &CosmicMapped) -> Option<FloatTicket> {
+        let node_id = window.tiling_node_id.lock().unwrap().clone()?;
+        let output = self.output_for_element(window)?.clone();
+        let tree = &self.queues.get(&output).unwrap().trees.back().unwrap().0;
+
+        let parent = tree.get(&node_id).unwrap().parent().cloned();
+        let (idx, orientation, sibling) = match parent.as_ref() {
+            Some(parent_id) => {
+                let children = tree
+                    .children_ids(parent_id)
+                    .unwrap()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let idx = children.iter().position(|id| id == &node_id).unwrap();
+                // a group with a single child may not have been flattened yet
+                let sibling = match children.get(idx + 1) {
+                    Some(next) => Some((next.clone(), false)),
+                    None => idx
+                        .checked_sub(1)
+                        .map(|prev| (children[prev].clone(), true)),
+                };
+                (
+                    idx,
+                    tree.get(parent_id).unwrap().data().orientation(),
+                    sibling,
+                )
+            }
+            None => (0, Orientation::Vertical, None),
+        };
+
+        self.unmap(window)?;
+        Some(FloatTicket {
+            window: window.clone(),
+            output,
+            parent,
+            idx,
+            orientation,
+            sibling,
+        })
+    }
+
+    /// Tiles a window unmapped by `float_out` again, at its old slot if that still exists
+    pub fn retile(&mut self, ticket: FloatTicket, seat: &Seat<State>) {
+        let window = ticket.window;
+        if self.is_mapped(&window) {
+            return;
+        }
+        let Some(queue) = self.queues.get_mut(&ticket.output) else {
+            self.map(window, seat, std::iter::empty::<&CosmicMapped>(), None, None);
+            return;
+        };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let new_window = Node::new(Data::Mapped {
+            mapped: window.clone(),
+            last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+            sticky_end: false,
+            always_on_top: false,
+        });
+
+        let parent = ticket.parent.filter(|parent_id| {
+            tree.get(parent_id)
+                .map(|node| {
+                    node.data().is_group() && node.data().orientation() == ticket.orientation
+                })
+                .unwrap_or(false)
+        });
+        let sibling = ticket
+            .sibling
+            .filter(|(sibling_id, _)| tree.get(sibling_id).is_ok());
+
+        let new_id = match (parent, sibling) {
+            (Some(parent_id), _) => {
+                let idx = ticket.idx.min(tree.get(&parent_id).unwrap().data().len());
+                let new_id = tree
+                    .insert(new_window, InsertBehavior::UnderNode(&parent_id))
+                    .unwrap();
+                tree.make_nth_sibling(&new_id, idx).unwrap();
+                TilingLayout::add_to_group(
+                    &mut tree,
+                    &parent_id,
+                    idx,
+                    self.config.sizing_policy,
+                    self.config.min_size_on(&ticket.output),
+                );
                 new_id
-            } else {
-                tree.insert(new_window, InsertBehavior::AsRoot).unwrap()
             }
-        } else {
-            let last_active = focus_stack
-                .and_then(|focus_stack| TilingLayout::last_active_window(&mut tree, focus_stack));
-
-            if let Some((ref node_id, mut last_active_window)) = last_active {
-                if window.is_window() && last_active_window.is_stack() {
-                    let surface = window.active_window();
-                    last_active_window
-                        .stack_ref_mut()
-                        .unwrap()
-                        .add_window(surface, None);
-                    return;
-                }
-
-                let orientation = {
-                    let window_size = tree.get(node_id).unwrap().data().geometry().size;
-                    if window_size.w > window_size.h {
-                        Orientation::Vertical
-                    } else {
-                        Orientation::Horizontal
-                    }
-                };
+            // the group is gone, but we can still split our old neighbor
+            (None, Some((sibling_id, after))) => {
                 let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
-                TilingLayout::new_group(&mut tree, &node_id, &new_id, orientation).unwrap();
-                new_id
-            } else {
-                // nothing? then we add to the root
-                if let Some(root_id) = tree.root_node_id().cloned() {
-                    let orientation = {
-                        let output_size = output.geometry().size;
-                        if output_size.w > output_size.h {
-                            Orientation::Vertical
-                        } else {
-                            Orientation::Horizontal
-                        }
-                    };
-                    let new_id = tree.insert(new_window, InsertBehavior::AsRoot).unwrap();
-                    TilingLayout::new_group(&mut tree, &root_id, &new_id, orientation).unwrap();
-                    new_id
-                } else {
-                    tree.insert(new_window, InsertBehavior::AsRoot).unwrap()
+                TilingLayout::new_group(&mut tree, &sibling_id, &new_id, ticket.orientation)
+                    .unwrap();
+                if !after {
+                    tree.make_first_sibling(&new_id).unwrap();
                 }
+                new_id
+            }
+            (None, None) => {
+                self.map(
+                    window,
+                    seat,
+                    std::iter::empty::<&CosmicMapped>(),
+                    None,
+                    None,
+                );
+                return;
             }
         };
 
-        *window.tiling_node_id.lock().unwrap() = Some(window_id);
+        window.output_enter(&ticket.output, window.bbox());
+        window.set_bounds(ticket.output.geometry().size);
+        *window.tiling_node_id.lock().unwrap() = Some(new_id);
+
+        TilingLayout::keep_sticky_last(&mut tree);
+        let blocker = TilingLayout::update_positions(
+            &ticket.output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
     }
 
-    pub fn unmap(&mut self, window: &CosmicMapped) -> Option<Output> {
+    /// Returns the output `window` was mapped on and the target that should receive focus in its place
+    pub fn unmap(
+        &mut self,
+        window: &CosmicMapped,
+    ) -> Option<(Output, Option<KeyboardFocusTarget>)> {
+        let swallowed = self.swallowed.get(window).cloned();
+        if let Some(output) = self.unswallow(window) {
+            return Some((output, swallowed.map(KeyboardFocusTarget::from)));
+        }
+
         let output = {
             let node_id = window.tiling_node_id.lock().unwrap().clone()?;
             self.queues
@@ -544,14 +3417,90 @@ impl TilingLayout {
                 .map(|(o, _)| o.output.clone())?
         };
 
+        let focus = self.neighbor_for_close(window);
         self.unmap_window_internal(window);
+        self.input_passthrough.remove(window);
 
         window.output_leave(&output);
         window.set_tiled(false);
-        Some(output)
+        Some((output, focus))
+    }
+
+    // Removes a node and fixes up its parent group, without flattening the tree
+    fn remove_from_tree(tree: &mut Tree<Data>, node_id: NodeId) {
+        if let Err(err) = TilingLayout::try_remove_from_tree(tree, node_id) {
+            warn!(?err, "Failed to remove node from tree.");
+        }
+    }
+
+    fn try_remove_from_tree(tree: &mut Tree<Data>, node_id: NodeId) -> Result<(), NodeIdError> {
+        let parent_id = tree
+            .get(&node_id)
+            .ok()
+            .and_then(|node| node.parent())
+            .cloned();
+        let position = match parent_id.as_ref() {
+            Some(parent_id) => Some(
+                tree.children_ids(&parent_id)?
+                    .position(|id| id == &node_id)
+                    .ok_or(NodeIdError::NodeIdNoLongerValid)?,
+            ),
+            None => None,
+        };
+        let parent_parent_id = parent_id.as_ref().and_then(|parent_id| {
+            tree.get(parent_id)
+                .ok()
+                .and_then(|node| node.parent())
+                .cloned()
+        });
+
+        // remove self
+        let _ = tree.remove_node(node_id, RemoveBehavior::DropChildren);
+
+        // fixup parent node
+        match parent_id {
+            Some(id) => {
+                let position = position.unwrap();
+                let group = tree.get_mut(&id)?.data_mut();
+                if !group.is_group() {
+                    return Err(NodeIdError::InvalidNodeIdForTree);
+                }
+
+                if group.len() > 2 {
+                    group.remove_window(position);
+                } else {
+                    trace!("Removing Group");
+                    let other_child = tree
+                        .children_ids(&id)?
+                        .cloned()
+                        .next()
+                        .ok_or(NodeIdError::NodeIdNoLongerValid)?;
+                    let fork_pos = match parent_parent_id.as_ref() {
+                        Some(parent_id) => tree.children_ids(parent_id)?.position(|i| i == &id),
+                        None => None,
+                    };
+                    let _ = tree.remove_node(id.clone(), RemoveBehavior::OrphanChildren);
+                    tree.move_node(
+                        &other_child,
+                        parent_parent_id
+                            .as_ref()
+                            .map(|parent_id| MoveBehavior::ToParent(parent_id))
+                            .unwrap_or(MoveBehavior::ToRoot),
+                    )?;
+                    if let Some(old_pos) = fork_pos {
+                        tree.make_nth_sibling(&other_child, old_pos)?;
+                    }
+                }
+            }
+            None => {} // root
+        }
+        Ok(())
     }
 
     fn unmap_window_internal(&mut self, mapped: &CosmicMapped) {
+        if self.sticky_windows.remove(mapped) {
+            self.remove_sticky_mirrors(mapped);
+        }
         let tiling_node_id = mapped.tiling_node_id.lock().unwrap().as_ref().cloned();
         if let Some(node_id) = tiling_node_id {
             if let Some((output, queue)) = self.queues.iter_mut().find(|(_, queue)| {
@@ -562,104 +3511,666 @@ impl TilingLayout {
             }) {
                 let mut tree = queue.trees.back().unwrap().0.copy_clone();
 
-                let parent_id = tree
-                    .get(&node_id)
-                    .ok()
-                    .and_then(|node| node.parent())
-                    .cloned();
-                let position = parent_id.as_ref().and_then(|parent_id| {
-                    tree.children_ids(&parent_id)
+                trace!(?mapped, "Remove window.");
+                if let Err(err) = TilingLayout::try_remove_from_tree(&mut tree, node_id) {
+                    warn!(
+                        ?mapped,
+                        ?err,
+                        "Failed to remove window, keeping the layout unchanged."
+                    );
+                    return;
+                }
+
+                TilingLayout::flatten(&mut tree);
+                let blocker = TilingLayout::update_positions(
+                    &output.output,
+                    &mut tree,
+                    &self.config,
+                    self.geometry_hook.as_ref(),
+                    queue.overscan,
+                    queue.reserved_zone,
+                );
+                queue.push_tree(tree, self.config.animation_duration, blocker);
+                queue.forget_focus(mapped);
+            }
+        }
+    }
+
+    /// Moves `moving` next to `anchor`, on the `side` of it, possibly across outputs
+    pub fn move_window_beside(
+        &mut self,
+        moving: &CosmicMapped,
+        anchor: &CosmicMapped,
+        side: Direction,
+        seat: &Seat<State>,
+    ) {
+        if moving == anchor || !self.is_mapped(moving) || !self.is_mapped(anchor) {
+            return;
+        }
+        self.record_current_focus(seat);
+
+        let src_output = self.output_for_element(moving).unwrap().clone();
+        let dst_output = self.output_for_element(anchor).unwrap().clone();
+        let moving_id = moving.tiling_node_id.lock().unwrap().clone().unwrap();
+
+        let src_queue = self.queues.get_mut(&src_output).unwrap();
+        let mut src_tree = src_queue.trees.back().unwrap().0.copy_clone();
+        let data = src_tree.get(&moving_id).unwrap().data().clone();
+        if let Err(err) = TilingLayout::try_remove_from_tree(&mut src_tree, moving_id) {
+            warn!(
+                ?moving,
+                ?err,
+                "Failed to remove window, keeping the layout unchanged."
+            );
+            return;
+        }
+
+        let mut tree = if src_output == dst_output {
+            src_tree
+        } else {
+            TilingLayout::flatten(&mut src_tree);
+            let blocker = TilingLayout::update_positions(
+                &src_output,
+                &mut src_tree,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                src_queue.overscan,
+                src_queue.reserved_zone,
+            );
+            src_queue.push_tree(src_tree, self.config.animation_duration, blocker);
+
+            moving.output_leave(&src_output);
+            moving.output_enter(&dst_output, moving.bbox());
+            moving.set_bounds(dst_output.geometry().size);
+            self.queues
+                .get(&dst_output)
+                .unwrap()
+                .trees
+                .back()
+                .unwrap()
+                .0
+                .copy_clone()
+        };
+
+        let anchor_id = anchor.tiling_node_id.lock().unwrap().clone().unwrap();
+        let orientation = match side {
+            Direction::Left | Direction::Right => Orientation::Vertical,
+            Direction::Up | Direction::Down => Orientation::Horizontal,
+        };
+        let after = matches!(side, Direction::Right | Direction::Down);
+        let parent_id = tree
+            .get(&anchor_id)
+            .unwrap()
+            .parent()
+            .cloned()
+            .filter(|parent_id| tree.get(parent_id).unwrap().data().orientation() == orientation);
+
+        let new_id = match parent_id {
+            // the anchor already sits in a fitting group, become a sibling
+            Some(parent_id) => {
+                let idx = tree
+                    .children_ids(&parent_id)
+                    .unwrap()
+                    .position(|id| id == &anchor_id)
+                    .unwrap()
+                    + after as usize;
+                let new_id = tree
+                    .insert(Node::new(data), InsertBehavior::UnderNode(&parent_id))
+                    .unwrap();
+                tree.make_nth_sibling(&new_id, idx).unwrap();
+                TilingLayout::add_to_group(
+                    &mut tree,
+                    &parent_id,
+                    idx,
+                    self.config.sizing_policy,
+                    self.config.min_size_on(&dst_output),
+                );
+                new_id
+            }
+            None => {
+                let new_id = tree
+                    .insert(Node::new(data), InsertBehavior::AsRoot)
+                    .unwrap();
+                TilingLayout::new_group(&mut tree, &anchor_id, &new_id, orientation).unwrap();
+                if !after {
+                    tree.make_first_sibling(&new_id).unwrap();
+                }
+                new_id
+            }
+        };
+        *moving.tiling_node_id.lock().unwrap() = Some(new_id);
+
+        TilingLayout::flatten(&mut tree);
+        let dst_queue = self.queues.get_mut(&dst_output).unwrap();
+        let blocker = TilingLayout::update_positions(
+            &dst_output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            dst_queue.overscan,
+            dst_queue.reserved_zone,
+        );
+        dst_queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Moves `window` from `source` into the group of the focused node on the active output
+    pub fn pull_from_output(
+        &mut self,
+        source: &Output,
+        window: &CosmicMapped,
+        seat: &Seat<State>,
+        toplevel_info: &mut ToplevelInfoState<State, CosmicSurface>,
+    ) {
+        let dst_output = seat.active_output();
+        if &dst_output == source || !self.queues.contains_key(&dst_output) {
+            return;
+        }
+        let Some(window_id) = window.tiling_node_id.lock().unwrap().clone() else { return };
+        let Some(src_queue) = self.queues.get_mut(source) else { return };
+        let mut src_tree = src_queue.trees.back().unwrap().0.copy_clone();
+        if !src_tree
+            .get(&window_id)
+            .map(|node| node.data().is_mapped(Some(window)))
+            .unwrap_or(false)
+        {
+            return;
+        }
+        self.record_current_focus(seat);
+
+        let src_queue = self.queues.get_mut(source).unwrap();
+        let data = src_tree.get(&window_id).unwrap().data().clone();
+        TilingLayout::remove_from_tree(&mut src_tree, window_id);
+        TilingLayout::flatten(&mut src_tree);
+        let blocker = TilingLayout::update_positions(
+            source,
+            &mut src_tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            src_queue.overscan,
+            src_queue.reserved_zone,
+        );
+        src_queue.push_tree(src_tree, self.config.animation_duration, blocker);
+
+        for (toplevel, _) in window.windows() {
+            toplevel_info.toplevel_leave_output(&toplevel, source);
+            toplevel_info.toplevel_enter_output(&toplevel, &dst_output);
+        }
+        window.output_leave(source);
+        window.output_enter(&dst_output, window.bbox());
+        window.set_bounds(dst_output.geometry().size);
+
+        let dst_queue = self.queues.get_mut(&dst_output).unwrap();
+        let mut tree = dst_queue.trees.back().unwrap().0.copy_clone();
+        let focused = seat
+            .get_keyboard()
+            .unwrap()
+            .current_focus()
+            .and_then(|target| TilingLayout::currently_focused_node(&tree, &dst_output, target))
+            .map(|(id, _)| id);
+
+        match focused {
+            Some(focused_id) => {
+                let new_id = match tree.get(&focused_id).unwrap().parent().cloned() {
+                    Some(group_id) => {
+                        let idx = tree
+                            .children_ids(&group_id)
+                            .unwrap()
+                            .position(|id| id == &focused_id)
+                            .unwrap()
+                            + 1;
+                        let new_id = tree
+                            .insert(Node::new(data), InsertBehavior::UnderNode(&group_id))
+                            .unwrap();
+                        tree.make_nth_sibling(&new_id, idx).unwrap();
+                        TilingLayout::add_to_group(
+                            &mut tree,
+                            &group_id,
+                            idx,
+                            self.config.sizing_policy,
+                            self.config.min_size_on(&dst_output),
+                        );
+                        new_id
+                    }
+                    None => {
+                        let orientation = split_orientation(
+                            tree.get(&focused_id).unwrap().data().geometry().size,
+                            self.config.preferred_tile_aspect,
+                        );
+                        let new_id = tree
+                            .insert(Node::new(data), InsertBehavior::AsRoot)
+                            .unwrap();
+                        TilingLayout::new_group(&mut tree, &focused_id, &new_id, orientation)
+                            .unwrap();
+                        new_id
+                    }
+                };
+                *window.tiling_node_id.lock().unwrap() = Some(new_id);
+                TilingLayout::keep_sticky_last(&mut tree);
+            }
+            None => {
+                TilingLayout::map_to_tree(
+                    &mut tree,
+                    window.clone(),
+                    &dst_output,
+                    None::<std::iter::Empty<&CosmicMapped>>,
+                    None,
+                    self.config.preferred_tile_aspect,
+                    None,
+                    self.config.new_window_fraction,
+                );
+            }
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &dst_output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            dst_queue.overscan,
+            dst_queue.reserved_zone,
+        );
+        dst_queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Returns the `index`th window of `output` (starting at 0), in reading order
+    pub fn window_at_index(&self, output: &Output, index: usize) -> Option<CosmicMapped> {
+        let tree = &self.queues.get(output)?.trees.back().unwrap().0;
+        let root = tree.root_node_id()?;
+
+        let mut windows = tree
+            .traverse_pre_order(root)
+            .unwrap()
+            .filter_map(|node| match node.data() {
+                Data::Mapped {
+                    mapped,
+                    last_geometry,
+                    ..
+                } => Some((last_geometry.loc, mapped)),
+                Data::Group { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        windows.sort_by_key(|(loc, _)| (loc.y, loc.x));
+        windows
+            .into_iter()
+            .nth(index)
+            .map(|(_, mapped)| mapped.clone())
+    }
+
+    /// Split ratios of all groups of `output` in depth-first order,
+    /// each group as its number of children followed by their fractions of its length
+    pub fn export_ratios(&self, output: &Output) -> Vec<f64> {
+        let Some(queue) = self.queues.get(output) else { return Vec::new() };
+        let tree = &queue.trees.back().unwrap().0;
+        let Some(root_id) = tree.root_node_id() else { return Vec::new() };
+
+        let mut ratios = Vec::new();
+        for node in tree.traverse_pre_order(root_id).unwrap() {
+            if let Data::Group { sizes, .. } = node.data() {
+                let total = sizes.iter().sum::<i32>().max(1) as f64;
+                ratios.push(sizes.len() as f64);
+                ratios.extend(sizes.iter().map(|size| *size as f64 / total));
+            }
+        }
+        ratios
+    }
+
+    /// Applies ratios previously returned by `export_ratios` to the groups of `output`.
+    /// Groups with a different number of children are left untouched.
+    pub fn import_ratios(&mut self, output: &Output, ratios: &[f64]) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let Some(root_id) = tree.root_node_id().cloned() else { return };
+
+        let mut ratios = ratios;
+        for node_id in tree
+            .traverse_pre_order_ids(&root_id)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .into_iter()
+        {
+            let Data::Group { sizes, .. } = tree.get_mut(&node_id).unwrap().data_mut() else {
+                continue;
+            };
+            let Some((count, rest)) = ratios.split_first() else { break };
+            let count = *count as usize;
+            if rest.len() < count {
+                break;
+            }
+            let (group_ratios, rest) = rest.split_at(count);
+            ratios = rest;
+            if count != sizes.len() || group_ratios.iter().any(|ratio| !(*ratio > 0.)) {
+                continue;
+            }
+
+            let total = sizes.iter().sum::<i32>();
+            let ratio_sum = group_ratios.iter().sum::<f64>();
+            for (size, ratio) in sizes.iter_mut().zip(group_ratios) {
+                *size = (total as f64 * ratio / ratio_sum).round() as i32;
+            }
+            let missing = total - sizes.iter().sum::<i32>();
+            *sizes.last_mut().unwrap() += missing;
+        }
+
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Moves every window of `output` one slot further in reading order, keeping the shape of the tree
+    pub fn rotate_output(&mut self, output: &Output, reverse: bool) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let Some(root_id) = tree.root_node_id().cloned() else { return };
+
+        let mut leaves = tree
+            .traverse_pre_order_ids(&root_id)
+            .unwrap()
+            .filter_map(|id| match tree.get(&id).unwrap().data() {
+                Data::Mapped { last_geometry, .. } => Some((last_geometry.loc, id)),
+                Data::Group { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        if leaves.len() < 2 {
+            return;
+        }
+        leaves.sort_by_key(|(loc, _)| (loc.y, loc.x));
+        let leaves = leaves.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+
+        let mut payloads = leaves
+            .iter()
+            .map(|id| tree.get(id).unwrap().data().clone())
+            .collect::<Vec<_>>();
+        if reverse {
+            payloads.rotate_left(1);
+        } else {
+            payloads.rotate_right(1);
+        }
+        for (id, data) in leaves.into_iter().zip(payloads) {
+            if let Data::Mapped { mapped, .. } = &data {
+                *mapped.tiling_node_id.lock().unwrap() = Some(id.clone());
+            }
+            *tree.get_mut(&id).unwrap().data_mut() = data;
+        }
+
+        // the windows still carry their old geometries, use that as the start of the animation
+        queue.push_tree(tree.copy_clone(), Duration::ZERO, None);
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    /// Union of the committed geometries of all windows tiled on `output`
+    pub fn content_bounds(&self, output: &Output) -> Option<Rectangle<i32, Logical>> {
+        self.mapped()
+            .filter(|(o, _, _)| *o == output)
+            .map(|(_, _, geo)| geo)
+            .reduce(|bounds, geo| bounds.merge(geo))
+    }
+
+    /// Center lines of the gaps between sibling slots on `output`, in output-local coordinates,
+    /// tagged with the orientation of their group
+    pub fn divider_segments(
+        &self,
+        output: &Output,
+    ) -> Vec<(Point<i32, Logical>, Point<i32, Logical>, Orientation)> {
+        let Some(queue) = self.queues.get(output) else { return Vec::new() };
+        let tree = &queue.trees.back().unwrap().0;
+        let Some(root_id) = tree.root_node_id() else { return Vec::new() };
+
+        let mut segments = Vec::new();
+        for node in tree.traverse_pre_order(root_id).unwrap() {
+            let Data::Group {
+                orientation,
+                last_geometry,
+                ..
+            } = node.data() else { continue };
+            let geometries = node
+                .children()
+                .iter()
+                .map(|child_id| *tree.get(child_id).unwrap().data().geometry())
+                .collect::<Vec<_>>();
+            for pair in geometries.windows(2) {
+                let (first, second) = (pair[0], pair[1]);
+                segments.push(match orientation {
+                    Orientation::Vertical => {
+                        let x = (first.loc.x + first.size.w + second.loc.x) / 2;
+                        (
+                            Point::from((x, last_geometry.loc.y)),
+                            Point::from((x, last_geometry.loc.y + last_geometry.size.h)),
+                            *orientation,
+                        )
+                    }
+                    Orientation::Horizontal => {
+                        let y = (first.loc.y + first.size.h + second.loc.y) / 2;
+                        (
+                            Point::from((last_geometry.loc.x, y)),
+                            Point::from((last_geometry.loc.x + last_geometry.size.w, y)),
+                            *orientation,
+                        )
+                    }
+                });
+            }
+        }
+        segments
+    }
+
+    pub fn output_for_element(&self, elem: &CosmicMapped) -> Option<&Output> {
+        self.mapped().find_map(|(o, m, _)| (m == elem).then_some(o))
+    }
+
+    // TODO: Move would needs this to be accurate during animations
+    pub fn element_geometry(&self, elem: &CosmicMapped) -> Option<Rectangle<i32, Logical>> {
+        if let Some(id) = elem.tiling_node_id.lock().unwrap().as_ref() {
+            if let Some(output) = self.output_for_element(elem) {
+                let (output_data, queue) = self.queues.get_key_value(output).unwrap();
+                let node = queue.trees.back().unwrap().0.get(id).ok()?;
+                let data = node.data();
+                assert!(data.is_mapped(Some(elem)));
+                let mut geo = *data.geometry();
+                if self.config.query_mode == QueryMode::Visible {
+                    // newly mapped windows might not be visible yet
+                    if let Ok(node) = queue.visible_tree(self.config.animation_curve).get(id) {
+                        geo = *node.data().geometry();
+                    }
+                }
+                geo.loc += output_data.location;
+                return Some(geo);
+            }
+        }
+        None
+    }
+
+    /// Where `elem` currently is on screen, following any running animation
+    pub fn element_geometry_animated(
+        &self,
+        elem: &CosmicMapped,
+    ) -> Option<Rectangle<i32, Logical>> {
+        let id = elem.tiling_node_id.lock().unwrap().clone()?;
+        let output = self.output_for_element(elem)?;
+        let (output_data, queue) = self.queues.get_key_value(output).unwrap();
+
+        let reference = queue
+            .trees
+            .front()
+            .unwrap()
+            .0
+            .get(&id)
+            .ok()
+            .map(|node| *node.data().geometry());
+        let mut geo = match queue.animation_percentage(self.config.animation_curve) {
+            Some(percentage) => {
+                let target = *queue.trees.get(1)?.0.get(&id).ok()?.data().geometry();
+                match reference {
+                    Some(reference) => interpolate_geometry(reference, target, percentage),
+                    None => target,
+                }
+            }
+            // windows that didn't make it into the displayed tree yet are heading to their target
+            None => match reference {
+                Some(reference) => reference,
+                None => *queue
+                    .trees
+                    .back()
+                    .unwrap()
+                    .0
+                    .get(&id)
+                    .ok()?
+                    .data()
+                    .geometry(),
+            },
+        };
+        geo.loc += output_data.location;
+        Some(geo)
+    }
+
+    /// Ratio of the minimum size of the group's children to the group's actual size
+    pub fn group_fill(&self, output: &Output, group: &NodeId) -> Option<f64> {
+        let tree = &self.queues.get(output)?.trees.back().unwrap().0;
+        let node = tree.get(group).ok()?;
+        let Data::Group { orientation, last_geometry, .. } = node.data() else { return None };
+
+        let length = match orientation {
+            Orientation::Vertical => last_geometry.size.w,
+            Orientation::Horizontal => last_geometry.size.h,
+        };
+        if length <= 0 {
+            return None;
+        }
+        let used = self.config.min_node_length(tree, group, *orientation);
+        Some(used as f64 / length as f64)
+    }
+
+    pub fn group_children_targets(
+        &self,
+        output: &Output,
+        group: &NodeId,
+    ) -> Vec<KeyboardFocusTarget> {
+        let Some(queue) = self.queues.get(output) else { return Vec::new() };
+        let tree = &queue.trees.back().unwrap().0;
+        let Ok(children) = tree.children_ids(group) else { return Vec::new() };
+
+        children
+            .map(|child_id| match tree.get(child_id).unwrap().data() {
+                Data::Mapped { mapped, .. } => mapped.clone().into(),
+                Data::Group { alive, .. } => WindowGroup {
+                    node: child_id.clone(),
+                    output: output.downgrade(),
+                    alive: Arc::downgrade(alive),
+                    // focusing in, should select the first child
+                    focus_stack: tree
+                        .children_ids(child_id)
                         .unwrap()
-                        .position(|id| id == &node_id)
-                });
-                let parent_parent_id = parent_id.as_ref().and_then(|parent_id| {
-                    tree.get(parent_id)
-                        .ok()
-                        .and_then(|node| node.parent())
+                        .next()
                         .cloned()
-                });
-
-                // remove self
-                trace!(?mapped, "Remove window.");
-                let _ = tree.remove_node(node_id, RemoveBehavior::DropChildren);
-
-                // fixup parent node
-                match parent_id {
-                    Some(id) => {
-                        let position = position.unwrap();
-                        let group = tree.get_mut(&id).unwrap().data_mut();
-                        assert!(group.is_group());
-
-                        if group.len() > 2 {
-                            group.remove_window(position);
-                        } else {
-                            trace!("Removing Group");
-                            let other_child =
-                                tree.children_ids(&id).unwrap().cloned().next().unwrap();
-                            let fork_pos = parent_parent_id.as_ref().and_then(|parent_id| {
-                                tree.children_ids(parent_id).unwrap().position(|i| i == &id)
-                            });
-                            let _ = tree.remove_node(id.clone(), RemoveBehavior::OrphanChildren);
-                            tree.move_node(
-                                &other_child,
-                                parent_parent_id
-                                    .as_ref()
-                                    .map(|parent_id| MoveBehavior::ToParent(parent_id))
-                                    .unwrap_or(MoveBehavior::ToRoot),
-                            )
-                            .unwrap();
-                            if let Some(old_pos) = fork_pos {
-                                tree.make_nth_sibling(&other_child, old_pos).unwrap();
-                            }
-                        }
-                    }
-                    None => {} // root
+                        .into_iter()
+                        .collect(),
                 }
+                .into(),
+            })
+            .collect()
+    }
 
-                let blocker = TilingLayout::update_positions(&output.output, &mut tree, self.gaps);
-                queue.push_tree(tree, ANIMATION_DURATION, blocker);
+    pub fn move_current_node(&mut self, direction: Direction, seat: &Seat<State>) -> MoveResult {
+        self.record_current_focus(seat);
+        let output = seat.active_output();
+        if let Some(Err(err)) = self
+            .queues
+            .get(&output)
+            .map(|queue| TilingLayout::check_tree(&queue.trees.back().unwrap().0))
+        {
+            warn!(?err, "Inconsistent tree, refusing to move.");
+            return MoveResult::Done;
+        }
+        let queue_len = self.queues.get(&output).map(|queue| queue.trees.len());
+        let result = match self.move_current_node_internal(direction, seat) {
+            Ok(result) => result,
+            Err(err) => {
+                // nothing was pushed, the queued trees are untouched
+                warn!(?err, "Failed to move node, keeping the layout unchanged.");
+                return MoveResult::Done;
+            }
+        };
+        if let (Some(queue), Some(queue_len)) = (self.queues.get_mut(&output), queue_len) {
+            if let Err(err) = TilingLayout::check_tree(&queue.trees.back().unwrap().0) {
+                warn!(?err, "Move left the tree inconsistent, reverting.");
+                // the dropped trees were already recorded, undo them in the log as well
+                self.changes.record(TilingLayout::tree_changes(
+                    &output,
+                    &queue.trees.back().unwrap().0,
+                    &queue.trees[queue_len - 1].0,
+                ));
+                for (_, _, blocker) in queue.trees.drain(queue_len..) {
+                    self.pending_blockers.extend(blocker);
+                }
+                TilingLayout::restore_node_ids(&queue.trees.back().unwrap().0);
+                return MoveResult::Done;
             }
         }
+        if let (Some(observer), MoveResult::ShiftFocus(target) | MoveResult::MoveFurther(target)) =
+            (self.focus_observer.as_ref(), &result)
+        {
+            (observer.0)(target);
+        }
+        result
     }
 
-    pub fn output_for_element(&self, elem: &CosmicMapped) -> Option<&Output> {
-        self.mapped().find_map(|(o, m, _)| (m == elem).then_some(o))
+    // points the windows of `tree` back at their nodes, e.g. after dropping newer trees
+    fn restore_node_ids(tree: &Tree<Data>) {
+        let Some(root_id) = tree.root_node_id() else { return };
+        for node_id in tree.traverse_pre_order_ids(root_id).unwrap() {
+            if let Data::Mapped { mapped, .. } = tree.get(&node_id).unwrap().data() {
+                *mapped.tiling_node_id.lock().unwrap() = Some(node_id);
+            }
+        }
     }
 
-    // TODO: Move would needs this to be accurate during animations
-    pub fn element_geometry(&self, elem: &CosmicMapped) -> Option<Rectangle<i32, Logical>> {
-        if let Some(id) = elem.tiling_node_id.lock().unwrap().as_ref() {
-            if let Some(output) = self.output_for_element(elem) {
-                let (output_data, queue) = self.queues.get_key_value(output).unwrap();
-                let node = queue.trees.back().unwrap().0.get(id).ok()?;
-                let data = node.data();
-                assert!(data.is_mapped(Some(elem)));
-                let mut geo = *data.geometry();
-                geo.loc += output_data.location;
-                return Some(geo);
+    // every group has as many sizes as children and every child points back to its parent
+    fn check_tree(tree: &Tree<Data>) -> Result<(), NodeIdError> {
+        let Some(root_id) = tree.root_node_id() else { return Ok(()) };
+        for node_id in tree.traverse_pre_order_ids(root_id)? {
+            let node = tree.get(&node_id)?;
+            if node.data().is_group() && node.data().len() != node.children().len() {
+                return Err(NodeIdError::InvalidNodeIdForTree);
+            }
+            for child_id in node.children() {
+                if tree.get(child_id)?.parent() != Some(&node_id) {
+                    return Err(NodeIdError::InvalidNodeIdForTree);
+                }
             }
         }
-        None
+        Ok(())
     }
 
-    pub fn move_current_node<'a>(
+    fn move_current_node_internal(
         &mut self,
         direction: Direction,
         seat: &Seat<State>,
-    ) -> MoveResult {
+    ) -> Result<MoveResult, NodeIdError> {
         let output = seat.active_output();
-        let queue = self.queues.get_mut(&output).unwrap();
+        let Some(queue) = self.queues.get_mut(&output) else { return Ok(MoveResult::Done) };
         let mut tree = queue.trees.back().unwrap().0.copy_clone();
 
-        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return MoveResult::Done };
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return Ok(MoveResult::Done) };
         let Some((node_id, data)) = TilingLayout::currently_focused_node(&mut tree, &seat.active_output(), target) else {
-            return MoveResult::Done
+            return Ok(MoveResult::Done)
         };
 
         // stacks may handle movement internally
         if let FocusedNodeData::Window(window) = data.clone() {
             match window.handle_move(direction) {
-                StackMoveResult::Handled => return MoveResult::Done,
+                StackMoveResult::Handled => return Ok(MoveResult::Done),
                 StackMoveResult::MoveOut(surface, loop_handle) => {
                     let mapped: CosmicMapped = CosmicWindow::new(surface, loop_handle).into();
                     mapped.output_enter(&output, mapped.bbox());
@@ -671,22 +4182,31 @@ impl TilingLayout {
                     let new_node = Node::new(Data::Mapped {
                         mapped: mapped.clone(),
                         last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+                        sticky_end: false,
+                        always_on_top: false,
                     });
-                    let new_id = tree.insert(new_node, InsertBehavior::AsRoot).unwrap();
-                    TilingLayout::new_group(&mut tree, &node_id, &new_id, orientation).unwrap();
+                    let new_id = tree.insert(new_node, InsertBehavior::AsRoot)?;
+                    TilingLayout::new_group(&mut tree, &node_id, &new_id, orientation)?;
                     tree.make_nth_sibling(
                         &new_id,
                         match direction {
                             Direction::Left | Direction::Up => 0,
                             Direction::Right | Direction::Down => 1,
                         },
-                    )
-                    .unwrap();
+                    )?;
                     *mapped.tiling_node_id.lock().unwrap() = Some(new_id);
 
-                    let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-                    queue.push_tree(tree, ANIMATION_DURATION, blocker);
-                    return MoveResult::ShiftFocus(mapped.into());
+                    TilingLayout::flatten(&mut tree);
+                    let blocker = TilingLayout::update_positions(
+                        &output,
+                        &mut tree,
+                        &self.config,
+                        self.geometry_hook.as_ref(),
+                        queue.overscan,
+                        queue.reserved_zone,
+                    );
+                    queue.push_tree(tree, self.config.animation_duration, blocker);
+                    return Ok(MoveResult::ShiftFocus(mapped.into()));
                 }
                 StackMoveResult::Default => {} // continue normally
             }
@@ -694,8 +4214,8 @@ impl TilingLayout {
 
         let mut child_id = node_id.clone();
         // Without a parent to start with, just return
-        let Some(og_parent) = tree.get(&node_id).unwrap().parent().cloned() else {
-            return match data {
+        let Some(og_parent) = tree.get(&node_id)?.parent().cloned() else {
+            return Ok(match data {
                 FocusedNodeData::Window(window) => MoveResult::MoveFurther(window.into()),
                 FocusedNodeData::Group(focus_stack, alive) => MoveResult::MoveFurther(WindowGroup {
                     node: node_id,
@@ -703,26 +4223,24 @@ impl TilingLayout {
                     alive,
                     focus_stack,
                 }.into()),
-            }
+            })
         };
         let og_idx = tree
-            .children_ids(&og_parent)
-            .unwrap()
+            .children_ids(&og_parent)?
             .position(|id| id == &child_id)
-            .unwrap();
+            .ok_or(NodeIdError::NodeIdNoLongerValid)?;
         let mut maybe_parent = Some(og_parent.clone());
 
         while let Some(parent) = maybe_parent {
-            let parent_data = tree.get(&parent).unwrap().data();
+            let parent_data = tree.get(&parent)?.data();
             let orientation = parent_data.orientation();
             let len = parent_data.len();
 
             // which child are we?
             let idx = tree
-                .children_ids(&parent)
-                .unwrap()
+                .children_ids(&parent)?
                 .position(|id| id == &child_id)
-                .unwrap();
+                .ok_or(NodeIdError::NodeIdNoLongerValid)?;
 
             // if the orientation does not match..
             if matches!(
@@ -741,8 +4259,7 @@ impl TilingLayout {
                         Direction::Left | Direction::Right => Orientation::Vertical,
                         Direction::Up | Direction::Down => Orientation::Horizontal,
                     },
-                )
-                .unwrap();
+                )?;
                 tree.make_nth_sibling(
                     &node_id,
                     if direction == Direction::Left || direction == Direction::Up {
@@ -750,25 +4267,28 @@ impl TilingLayout {
                     } else {
                         1
                     },
-                )
-                .unwrap();
+                )?;
 
-                tree.get_mut(&og_parent)
-                    .unwrap()
-                    .data_mut()
-                    .remove_window(og_idx);
+                tree.get_mut(&og_parent)?.data_mut().remove_window(og_idx);
 
-                let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-                queue.push_tree(tree, ANIMATION_DURATION, blocker);
-                return MoveResult::Done;
+                TilingLayout::flatten(&mut tree);
+                let blocker = TilingLayout::update_positions(
+                    &output,
+                    &mut tree,
+                    &self.config,
+                    self.geometry_hook.as_ref(),
+                    queue.overscan,
+                    queue.reserved_zone,
+                );
+                queue.push_tree(tree, self.config.animation_duration, blocker);
+                return Ok(MoveResult::Done);
             }
 
             // now if the orientation matches
 
             // if we are not already in this group, we just move into it (up)
             if child_id != node_id {
-                tree.move_node(&node_id, MoveBehavior::ToParent(&parent))
-                    .unwrap();
+                tree.move_node(&node_id, MoveBehavior::ToParent(&parent))?;
                 tree.make_nth_sibling(
                     &node_id,
                     if direction == Direction::Left || direction == Direction::Up {
@@ -776,17 +4296,27 @@ impl TilingLayout {
                     } else {
                         idx + 1
                     },
-                )
-                .unwrap();
-                tree.get_mut(&parent).unwrap().data_mut().add_window(idx);
-                tree.get_mut(&og_parent)
-                    .unwrap()
-                    .data_mut()
-                    .remove_window(og_idx);
+                )?;
+                TilingLayout::add_to_group(
+                    &mut tree,
+                    &parent,
+                    idx,
+                    self.config.sizing_policy,
+                    self.config.min_size_on(&output),
+                );
+                tree.get_mut(&og_parent)?.data_mut().remove_window(og_idx);
 
-                let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-                queue.push_tree(tree, ANIMATION_DURATION, blocker);
-                return MoveResult::Done;
+                TilingLayout::flatten(&mut tree);
+                let blocker = TilingLayout::update_positions(
+                    &output,
+                    &mut tree,
+                    &self.config,
+                    self.geometry_hook.as_ref(),
+                    queue.overscan,
+                    queue.reserved_zone,
+                );
+                queue.push_tree(tree, self.config.animation_duration, blocker);
+                return Ok(MoveResult::Done);
             }
 
             // we can maybe move inside the group, if we don't run out of elements
@@ -807,22 +4337,20 @@ impl TilingLayout {
             } {
                 // if we can, we need to check the next element and move "into" it
                 let next_child_id = tree
-                    .children_ids(&parent)
-                    .unwrap()
+                    .children_ids(&parent)?
                     .nth(next_idx)
-                    .unwrap()
+                    .ok_or(NodeIdError::NodeIdNoLongerValid)?
                     .clone();
 
-                let result = if tree.get(&next_child_id).unwrap().data().is_stack()
-                    && tree.get(&node_id).unwrap().data().is_mapped(None)
-                    && !tree.get(&node_id).unwrap().data().is_stack()
+                let result = if self.config.stack_on_move
+                    && tree.get(&next_child_id)?.data().is_stack()
+                    && tree.get(&node_id)?.data().is_mapped(None)
+                    && !tree.get(&node_id)?.data().is_stack()
                     && len == 2
                 {
-                    let node = tree
-                        .remove_node(node_id, RemoveBehavior::DropChildren)
-                        .unwrap();
+                    let node = tree.remove_node(node_id, RemoveBehavior::DropChildren)?;
 
-                    let stack_data = tree.get_mut(&next_child_id).unwrap().data_mut();
+                    let stack_data = tree.get_mut(&next_child_id)?.data_mut();
                     let mut mapped = match stack_data {
                         Data::Mapped { mapped, .. } => mapped.clone(),
                         _ => unreachable!(),
@@ -833,6 +4361,8 @@ impl TilingLayout {
                         Data::Mapped { mapped, .. } => mapped.active_window(),
                         _ => unreachable!(),
                     };
+                    // last fallible step, before touching the stack
+                    tree.get_mut(&og_parent)?.data_mut().remove_window(og_idx);
                     stack.add_window(
                         surface,
                         match direction {
@@ -840,57 +4370,62 @@ impl TilingLayout {
                             _ => None,
                         },
                     );
-                    tree.get_mut(&og_parent)
-                        .unwrap()
-                        .data_mut()
-                        .remove_window(og_idx);
 
                     MoveResult::ShiftFocus(mapped.into())
-                } else if tree.get(&next_child_id).unwrap().data().is_group() && len == 2 {
+                } else if tree.get(&next_child_id)?.data().is_group() && len == 2 {
                     // if it is a group, we want to move into the group
-                    tree.move_node(&node_id, MoveBehavior::ToParent(&next_child_id))
-                        .unwrap();
-                    let group_orientation = tree.get(&next_child_id).unwrap().data().orientation();
+                    tree.move_node(&node_id, MoveBehavior::ToParent(&next_child_id))?;
+                    let group_orientation = tree.get(&next_child_id)?.data().orientation();
                     match (group_orientation, direction) {
                         (Orientation::Horizontal, Direction::Down)
                         | (Orientation::Vertical, Direction::Right) => {
-                            tree.make_first_sibling(&node_id).unwrap();
-                            tree.get_mut(&next_child_id)
-                                .unwrap()
-                                .data_mut()
-                                .add_window(0);
+                            tree.make_first_sibling(&node_id)?;
+                            TilingLayout::add_to_group(
+                                &mut tree,
+                                &next_child_id,
+                                0,
+                                self.config.sizing_policy,
+                                self.config.min_size_on(&output),
+                            );
                         }
                         (Orientation::Horizontal, Direction::Up)
                         | (Orientation::Vertical, Direction::Left) => {
-                            tree.make_last_sibling(&node_id).unwrap();
-                            let group = tree.get_mut(&next_child_id).unwrap().data_mut();
-                            group.add_window(group.len());
+                            tree.make_last_sibling(&node_id)?;
+                            let len = tree.get(&next_child_id)?.data().len();
+                            TilingLayout::add_to_group(
+                                &mut tree,
+                                &next_child_id,
+                                len,
+                                self.config.sizing_policy,
+                                self.config.min_size_on(&output),
+                            );
                         }
                         _ => {
                             // we want the middle
-                            let group_len = tree.get(&next_child_id).unwrap().data().len();
+                            let group_len = tree.get(&next_child_id)?.data().len();
                             if group_len % 2 == 0 {
-                                tree.make_nth_sibling(&node_id, group_len / 2).unwrap();
-                                tree.get_mut(&next_child_id)
-                                    .unwrap()
-                                    .data_mut()
-                                    .add_window(group_len / 2);
+                                tree.make_nth_sibling(&node_id, group_len / 2)?;
+                                TilingLayout::add_to_group(
+                                    &mut tree,
+                                    &next_child_id,
+                                    group_len / 2,
+                                    self.config.sizing_policy,
+                                    self.config.min_size_on(&output),
+                                );
                             } else {
                                 // we move again by making a new fork
                                 let old_id = tree
-                                    .children_ids(&next_child_id)
-                                    .unwrap()
+                                    .children_ids(&next_child_id)?
                                     .skip(group_len / 2)
                                     .next()
-                                    .unwrap()
+                                    .ok_or(NodeIdError::NodeIdNoLongerValid)?
                                     .clone();
                                 TilingLayout::new_group(
                                     &mut tree,
                                     &old_id,
                                     &node_id,
                                     !group_orientation,
-                                )
-                                .unwrap();
+                                )?;
                                 tree.make_nth_sibling(
                                     &node_id,
                                     if direction == Direction::Left || direction == Direction::Up {
@@ -898,31 +4433,25 @@ impl TilingLayout {
                                     } else {
                                         0
                                     },
-                                )
-                                .unwrap();
+                                )?;
                             }
                         }
                     };
-                    tree.get_mut(&og_parent)
-                        .unwrap()
-                        .data_mut()
-                        .remove_window(og_idx);
+                    tree.get_mut(&og_parent)?.data_mut().remove_window(og_idx);
 
                     MoveResult::Done
                 } else if len == 2 && child_id == node_id {
                     // if we are just us two in the group, lets swap
-                    tree.make_nth_sibling(&node_id, next_idx).unwrap();
+                    tree.make_nth_sibling(&node_id, next_idx)?;
                     // also swap sizes
-                    tree.get_mut(&og_parent)
-                        .unwrap()
+                    tree.get_mut(&og_parent)?
                         .data_mut()
                         .swap_windows(idx, next_idx);
 
                     MoveResult::Done
                 } else {
                     // else we make a new fork
-                    TilingLayout::new_group(&mut tree, &next_child_id, &node_id, orientation)
-                        .unwrap();
+                    TilingLayout::new_group(&mut tree, &next_child_id, &node_id, orientation)?;
                     tree.make_nth_sibling(
                         &node_id,
                         if direction == Direction::Left || direction == Direction::Up {
@@ -930,27 +4459,31 @@ impl TilingLayout {
                         } else {
                             0
                         },
-                    )
-                    .unwrap();
-                    tree.get_mut(&og_parent)
-                        .unwrap()
-                        .data_mut()
-                        .remove_window(og_idx);
+                    )?;
+                    tree.get_mut(&og_parent)?.data_mut().remove_window(og_idx);
 
                     MoveResult::Done
                 };
 
-                let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-                queue.push_tree(tree, ANIMATION_DURATION, blocker);
-                return result;
+                TilingLayout::flatten(&mut tree);
+                let blocker = TilingLayout::update_positions(
+                    &output,
+                    &mut tree,
+                    &self.config,
+                    self.geometry_hook.as_ref(),
+                    queue.overscan,
+                    queue.reserved_zone,
+                );
+                queue.push_tree(tree, self.config.animation_duration, blocker);
+                return Ok(result);
             }
 
             // We have reached the end of our parent group, try to move out even higher.
-            maybe_parent = tree.get(&parent).unwrap().parent().cloned();
+            maybe_parent = tree.get(&parent)?.parent().cloned();
             child_id = parent.clone();
         }
 
-        match data {
+        Ok(match data {
             FocusedNodeData::Window(window) => MoveResult::MoveFurther(window.into()),
             FocusedNodeData::Group(focus_stack, alive) => MoveResult::MoveFurther(
                 WindowGroup {
@@ -961,7 +4494,121 @@ impl TilingLayout {
                 }
                 .into(),
             ),
+        })
+    }
+
+    pub fn can_move(&self, direction: Direction, seat: &Seat<State>) -> bool {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get(&output) else { return false };
+        let tree = &queue.trees.back().unwrap().0;
+
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return false };
+        let Some((node_id, _)) = TilingLayout::currently_focused_node(tree, &output, target) else { return false };
+
+        // mirrors the structural checks of `move_current_node`
+        let mut child_id = node_id.clone();
+        let mut maybe_parent = tree.get(&node_id).unwrap().parent().cloned();
+        while let Some(parent) = maybe_parent {
+            let parent_data = tree.get(&parent).unwrap().data();
+            let orientation = parent_data.orientation();
+            let len = parent_data.len();
+            let idx = tree
+                .children_ids(&parent)
+                .unwrap()
+                .position(|id| id == &child_id)
+                .unwrap();
+
+            let matching = match direction {
+                Direction::Left | Direction::Right => orientation == Orientation::Vertical,
+                Direction::Up | Direction::Down => orientation == Orientation::Horizontal,
+            };
+            if !matching || child_id != node_id {
+                return true;
+            }
+
+            let within = match direction {
+                Direction::Right | Direction::Down => idx < (len - 1),
+                Direction::Left | Direction::Up => idx > 0,
+            };
+            if within {
+                return true;
+            }
+
+            maybe_parent = tree.get(&parent).unwrap().parent().cloned();
+            child_id = parent;
+        }
+
+        false
+    }
+
+    pub fn move_to_edge(&mut self, direction: Direction, seat: &Seat<State>) {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get_mut(&output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return };
+        let Some((node_id, _)) = TilingLayout::currently_focused_node(&tree, &output, target) else { return };
+        let Some(og_parent) = tree.get(&node_id).unwrap().parent().cloned() else { return };
+        let root_id = tree.root_node_id().cloned().unwrap();
+
+        let orientation = match direction {
+            Direction::Left | Direction::Right => Orientation::Vertical,
+            Direction::Up | Direction::Down => Orientation::Horizontal,
+        };
+        let first = direction == Direction::Left || direction == Direction::Up;
+
+        let og_idx = tree
+            .children_ids(&og_parent)
+            .unwrap()
+            .position(|id| id == &node_id)
+            .unwrap();
+        tree.get_mut(&og_parent)
+            .unwrap()
+            .data_mut()
+            .remove_window(og_idx);
+
+        let root_data = tree.get(&root_id).unwrap().data();
+        if root_data.is_group() && root_data.orientation() == orientation {
+            // the root already splits in the right direction, so just reparent us
+            tree.move_node(&node_id, MoveBehavior::ToParent(&root_id))
+                .unwrap();
+            if first {
+                tree.make_first_sibling(&node_id).unwrap();
+                TilingLayout::add_to_group(
+                    &mut tree,
+                    &root_id,
+                    0,
+                    self.config.sizing_policy,
+                    self.config.min_size_on(&output),
+                );
+            } else {
+                tree.make_last_sibling(&node_id).unwrap();
+                let len = tree.get(&root_id).unwrap().data().len();
+                TilingLayout::add_to_group(
+                    &mut tree,
+                    &root_id,
+                    len,
+                    self.config.sizing_policy,
+                    self.config.min_size_on(&output),
+                );
+            }
+        } else {
+            // ...else wrap the whole tree into a new root group (cleanup will remove any one-child-groups afterwards)
+            TilingLayout::new_group(&mut tree, &root_id, &node_id, orientation).unwrap();
+            tree.make_nth_sibling(&node_id, if first { 0 } else { 1 })
+                .unwrap();
         }
+
+        TilingLayout::flatten(&mut tree);
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
     }
 
     pub fn next_focus<'a>(
@@ -969,6 +4616,20 @@ impl TilingLayout {
         direction: FocusDirection,
         seat: &Seat<State>,
         focus_stack: impl Iterator<Item = &'a CosmicMapped> + 'a,
+    ) -> FocusResult {
+        self.record_current_focus(seat);
+        let result = self.next_focus_internal(direction, seat, focus_stack);
+        if let FocusResult::Some(KeyboardFocusTarget::Element(mapped)) = &result {
+            self.record_focus(&seat.active_output(), mapped);
+        }
+        result
+    }
+
+    fn next_focus_internal<'a>(
+        &mut self,
+        direction: FocusDirection,
+        seat: &Seat<State>,
+        focus_stack: impl Iterator<Item = &'a CosmicMapped> + 'a,
     ) -> FocusResult {
         let output = seat.active_output();
         let tree = &self.queues.get(&output).unwrap().trees.back().unwrap().0;
@@ -1116,48 +4777,366 @@ impl TilingLayout {
                                 point.to_f64()
                             };
 
-                            let distance = |candidate: &&NodeId| -> f64 {
-                                let geo = tree.get(candidate).unwrap().data().geometry();
-                                let mut point = geo.loc;
-                                match direction {
-                                    FocusDirection::Up => {
-                                        point += Point::from((geo.size.w / 2, geo.size.h))
-                                    }
-                                    FocusDirection::Down => point.x += geo.size.w,
-                                    FocusDirection::Right => point.y += geo.size.h / 2,
-                                    FocusDirection::Left => {
-                                        point += Point::from((geo.size.w, geo.size.h / 2))
-                                    }
-                                    _ => unreachable!(),
-                                };
-                                let point = point.to_f64();
-                                ((point.x - center.x).powi(2) + (point.y - center.y).powi(2)).sqrt()
-                            };
+                            let distance = |candidate: &&NodeId| -> f64 {
+                                let geo = tree.get(candidate).unwrap().data().geometry();
+                                let mut point = geo.loc;
+                                match direction {
+                                    FocusDirection::Up => {
+                                        point += Point::from((geo.size.w / 2, geo.size.h))
+                                    }
+                                    FocusDirection::Down => point.x += geo.size.w,
+                                    FocusDirection::Right => point.y += geo.size.h / 2,
+                                    FocusDirection::Left => {
+                                        point += Point::from((geo.size.w, geo.size.h / 2))
+                                    }
+                                    _ => unreachable!(),
+                                };
+                                let point = point.to_f64();
+                                ((point.x - center.x).powi(2) + (point.y - center.y).powi(2)).sqrt()
+                            };
+
+                            let focus_history = &self.queues.get(&output).unwrap().focus_history;
+                            let tie_break = |candidate: &&NodeId| {
+                                let recency = tree
+                                    .traverse_pre_order(candidate)
+                                    .unwrap()
+                                    .filter_map(|node| match node.data() {
+                                        Data::Mapped { mapped, .. } => {
+                                            focus_history.iter().position(|m| m == mapped)
+                                        }
+                                        Data::Group { .. } => None,
+                                    })
+                                    .min()
+                                    .unwrap_or(usize::MAX);
+                                let loc = tree.get(candidate).unwrap().data().geometry().loc;
+                                (recency, loc.y, loc.x)
+                            };
+
+                            node_id = tree
+                                .children_ids(node_id.as_ref().unwrap())
+                                .unwrap()
+                                .min_by(|node1, node2| {
+                                    let (dist1, dist2) =
+                                        (distance(node1).abs(), distance(node2).abs());
+                                    // equally close children go by recent focus, then top/left first
+                                    if (dist1 - dist2).abs() < 1.0 {
+                                        tie_break(node1).cmp(&tie_break(node2))
+                                    } else {
+                                        dist1.total_cmp(&dist2)
+                                    }
+                                });
+                        }
+                        Data::Mapped { mapped, .. } => {
+                            return FocusResult::Some(mapped.clone().into());
+                        }
+                    }
+                }
+            } else {
+                node_id = group.clone();
+            }
+        }
+
+        FocusResult::None
+    }
+
+    pub fn focus_nearest(&self, direction: FocusDirection, seat: &Seat<State>) -> FocusResult {
+        if matches!(direction, FocusDirection::In | FocusDirection::Out) {
+            return FocusResult::None;
+        }
+
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get(&output) else { return FocusResult::None };
+        let tree = &queue.trees.back().unwrap().0;
+        let Some(root_id) = tree.root_node_id() else { return FocusResult::None };
+
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return FocusResult::None };
+        let Some((focused_id, _)) = TilingLayout::currently_focused_node(tree, &output, target) else {
+            return FocusResult::None
+        };
+
+        let center = |geo: &Rectangle<i32, Logical>| -> Point<f64, Logical> {
+            Point::from((
+                geo.loc.x as f64 + geo.size.w as f64 / 2.0,
+                geo.loc.y as f64 + geo.size.h as f64 / 2.0,
+            ))
+        };
+        let origin = center(tree.get(&focused_id).unwrap().data().geometry());
+
+        tree.traverse_pre_order_ids(root_id)
+            .unwrap()
+            .filter(|id| {
+                id != &focused_id && !tree.ancestor_ids(id).unwrap().any(|a| a == &focused_id)
+            })
+            .filter_map(|id| match tree.get(&id).unwrap().data() {
+                Data::Mapped {
+                    mapped,
+                    last_geometry,
+                    ..
+                } => {
+                    let point = center(last_geometry);
+                    let (along, across) = match direction {
+                        FocusDirection::Left => (origin.x - point.x, point.y - origin.y),
+                        FocusDirection::Right => (point.x - origin.x, point.y - origin.y),
+                        FocusDirection::Up => (origin.y - point.y, point.x - origin.x),
+                        FocusDirection::Down => (point.y - origin.y, point.x - origin.x),
+                        _ => unreachable!(),
+                    };
+                    // only consider candidates inside a 90° cone around the direction
+                    (along > 0.0 && across.abs() <= along).then(|| (mapped, along.hypot(across)))
+                }
+                Data::Group { .. } => None,
+            })
+            .min_by(|(_, dist1), (_, dist2)| dist1.total_cmp(dist2))
+            .map(|(mapped, _)| FocusResult::Some(mapped.clone().into()))
+            .unwrap_or(FocusResult::None)
+    }
+
+    pub fn focus_depth(&self, seat: &Seat<State>) -> Option<usize> {
+        let output = seat.active_output();
+        let tree = &self.queues.get(&output)?.trees.back().unwrap().0;
+
+        let target = seat.get_keyboard().unwrap().current_focus()?;
+        let (node_id, _) = TilingLayout::currently_focused_node(tree, &output, target)?;
+        Some(tree.ancestor_ids(&node_id).unwrap().count())
+    }
+
+    /// Orientation of the group containing the focused node and the fractions of its children
+    pub fn focused_group_layout(&self, seat: &Seat<State>) -> Option<(Orientation, Vec<f64>)> {
+        let output = seat.active_output();
+        let tree = &self.queues.get(&output)?.trees.back().unwrap().0;
+
+        let target = seat.get_keyboard().unwrap().current_focus()?;
+        let (node_id, _) = TilingLayout::currently_focused_node(tree, &output, target)?;
+        let group_id = tree.get(&node_id).unwrap().parent()?;
+        match tree.get(group_id).unwrap().data() {
+            Data::Group {
+                orientation, sizes, ..
+            } => {
+                let length = sizes.iter().sum::<i32>() as f64;
+                Some((
+                    *orientation,
+                    sizes.iter().map(|size| *size as f64 / length).collect(),
+                ))
+            }
+            Data::Mapped { .. } => None,
+        }
+    }
+
+    /// Groups containing `window`, from the root down to its parent
+    pub fn ancestor_path(&self, window: &CosmicMapped) -> Option<Vec<(NodeId, Orientation)>> {
+        let node_id = window.tiling_node_id.lock().unwrap().clone()?;
+        let queue = self.queues.values().find(|queue| {
+            queue
+                .trees
+                .back()
+                .unwrap()
+                .0
+                .get(&node_id)
+                .map(|node| node.data().is_mapped(Some(window)))
+                .unwrap_or(false)
+        })?;
+        let tree = &queue.trees.back().unwrap().0;
+
+        let mut path = tree
+            .ancestor_ids(&node_id)
+            .unwrap()
+            .map(|id| (id.clone(), tree.get(id).unwrap().data().orientation()))
+            .collect::<Vec<_>>();
+        path.reverse();
+        Some(path)
+    }
+
+    /// Keyboard focus target for a tiled `window`, to re-assert focus on it after a layout change
+    pub fn focus_target_for(&self, window: &CosmicMapped) -> Option<KeyboardFocusTarget> {
+        if !self.is_mapped(window) {
+            return None;
+        }
+        if window.is_stack() {
+            window.stack_ref().unwrap().focus_stack();
+        }
+        Some(KeyboardFocusTarget::Element(window.clone()))
+    }
+
+    /// Highlights the group next to the focused node in `direction`.
+    /// Repeating the same direction selects the parent of the current selection instead.
+    pub fn select_group(
+        &mut self,
+        direction: Direction,
+        seat: &Seat<State>,
+    ) -> Option<WindowGroup> {
+        let output = seat.active_output();
+        let tree = &self.queues.get(&output)?.trees.back().unwrap().0;
+
+        let pending = self
+            .group_selection
+            .get(seat)
+            .filter(|(pending_direction, node_id, pending_output)| {
+                *pending_direction == direction
+                    && pending_output == &output
+                    && tree.get(node_id).is_ok()
+            })
+            .map(|(_, node_id, _)| node_id.clone());
+        let node_id = match pending {
+            Some(node_id) => tree
+                .get(&node_id)
+                .unwrap()
+                .parent()
+                .cloned()
+                .unwrap_or(node_id),
+            None => {
+                let target = seat.get_keyboard().unwrap().current_focus()?;
+                let (focused, _) = TilingLayout::currently_focused_node(tree, &output, target)?;
+                TilingLayout::neighbor_in_direction(tree, &focused, direction)
+                    .filter(|id| tree.get(id).unwrap().data().is_group())?
+            }
+        };
 
-                            node_id = tree
-                                .children_ids(node_id.as_ref().unwrap())
-                                .unwrap()
-                                .min_by(|node1, node2| {
-                                    distance(node1).abs().total_cmp(&distance(node2).abs())
-                                });
-                        }
-                        Data::Mapped { mapped, .. } => {
-                            return FocusResult::Some(mapped.clone().into());
-                        }
-                    }
+        let group = TilingLayout::window_group(tree, &node_id, &output)?;
+
+        self.group_selection
+            .insert(seat.clone(), (direction, node_id, output));
+        Some(group)
+    }
+
+    /// Moves focus from the focused group to the next group among its siblings in `direction`,
+    /// wrapping around at the ends if `wrap` is set
+    pub fn focus_sibling_group(
+        &mut self,
+        direction: Direction,
+        seat: &Seat<State>,
+        wrap: bool,
+    ) -> FocusResult {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get(&output) else { return FocusResult::None };
+        let tree = &queue.trees.back().unwrap().0;
+
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else {
+            return FocusResult::None;
+        };
+        let Some((node_id, FocusedNodeData::Group(_, _))) =
+            TilingLayout::currently_focused_node(tree, &output, target)
+        else {
+            return FocusResult::None;
+        };
+        let Some(parent_id) = tree.get(&node_id).unwrap().parent() else {
+            return FocusResult::None;
+        };
+
+        let groups = tree
+            .children_ids(parent_id)
+            .unwrap()
+            .filter(|id| tree.get(id).unwrap().data().is_group())
+            .collect::<Vec<_>>();
+        let idx = groups.iter().position(|id| **id == node_id).unwrap();
+        let next = match direction {
+            Direction::Left | Direction::Up => idx
+                .checked_sub(1)
+                .or_else(|| wrap.then(|| groups.len() - 1)),
+            Direction::Right | Direction::Down => Some(idx + 1)
+                .filter(|next| *next < groups.len())
+                .or(wrap.then_some(0)),
+        };
+
+        match next
+            .filter(|next| *next != idx)
+            .and_then(|next| TilingLayout::window_group(tree, groups[next], &output))
+        {
+            Some(group) => FocusResult::Some(group.into()),
+            None => FocusResult::Handled,
+        }
+    }
+
+    // focusing into the group goes to its first window
+    fn window_group(tree: &Tree<Data>, node_id: &NodeId, output: &Output) -> Option<WindowGroup> {
+        let alive = match tree.get(node_id).unwrap().data() {
+            Data::Group { alive, .. } => Arc::downgrade(alive),
+            Data::Mapped { .. } => return None,
+        };
+        let leaf = tree
+            .traverse_pre_order_ids(node_id)
+            .unwrap()
+            .find(|id| tree.get(id).unwrap().data().is_mapped(None))?;
+        let mut focus_stack = vec![leaf.clone()];
+        focus_stack.extend(
+            tree.ancestor_ids(&leaf)
+                .unwrap()
+                .take_while(|id| *id != node_id)
+                .cloned(),
+        );
+        Some(WindowGroup {
+            node: node_id.clone(),
+            output: output.downgrade(),
+            alive,
+            focus_stack,
+        })
+    }
+
+    pub fn clear_group_selection(&mut self, seat: &Seat<State>) {
+        self.group_selection.remove(seat);
+    }
+
+    // The sibling of `node_id` or one of its ancestors, that lies in `direction`
+    fn neighbor_in_direction(
+        tree: &Tree<Data>,
+        node_id: &NodeId,
+        direction: Direction,
+    ) -> Option<NodeId> {
+        let orientation = match direction {
+            Direction::Left | Direction::Right => Orientation::Vertical,
+            Direction::Up | Direction::Down => Orientation::Horizontal,
+        };
+        let mut child_id = node_id.clone();
+        while let Some(parent_id) = tree.get(&child_id).unwrap().parent().cloned() {
+            if tree.get(&parent_id).unwrap().data().orientation() == orientation {
+                let children = tree
+                    .children_ids(&parent_id)
+                    .unwrap()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let idx = children.iter().position(|id| id == &child_id).unwrap();
+                let neighbor = match direction {
+                    Direction::Left | Direction::Up => idx.checked_sub(1),
+                    Direction::Right | Direction::Down => Some(idx + 1),
+                };
+                if let Some(neighbor) = neighbor.and_then(|idx| children.get(idx)) {
+                    return Some(neighbor.clone());
                 }
-            } else {
-                node_id = group.clone();
             }
+            child_id = parent_id;
         }
+        None
+    }
 
-        FocusResult::None
+    /// Overrides the inner gap of the focused node's group, `None` uses the configured gap again
+    pub fn set_group_gap(&mut self, seat: &Seat<State>, gap: Option<i32>) {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get_mut(&output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return };
+        let Some((node_id, _)) = TilingLayout::currently_focused_node(&tree, &output, target) else { return };
+        let Some(group_id) = tree.get(&node_id).unwrap().parent().cloned() else { return };
+        match tree.get_mut(&group_id).unwrap().data_mut() {
+            Data::Group { gap_override, .. } => *gap_override = gap,
+            Data::Mapped { .. } => unreachable!(),
+        }
+
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
     }
 
     pub fn update_orientation<'a>(
         &mut self,
         new_orientation: Option<Orientation>,
         seat: &Seat<State>,
+        preserve_ratios: bool,
     ) {
         let output = seat.active_output();
         let Some(queue) = self.queues.get_mut(&output) else { return };
@@ -1168,187 +5147,534 @@ impl TilingLayout {
             TilingLayout::currently_focused_node(&tree, &seat.active_output(), target)
         {
             if let Some(group) = tree.get(&last_active).unwrap().parent().cloned() {
-                if let &mut Data::Group {
-                    ref mut orientation,
-                    ref mut sizes,
-                    ref last_geometry,
-                    ..
-                } = tree.get_mut(&group).unwrap().data_mut()
-                {
-                    let previous_length = match orientation {
-                        Orientation::Horizontal => last_geometry.size.h,
-                        Orientation::Vertical => last_geometry.size.w,
-                    };
-                    let new_orientation = new_orientation.unwrap_or(!*orientation);
-                    let new_length = match new_orientation {
-                        Orientation::Horizontal => last_geometry.size.h,
-                        Orientation::Vertical => last_geometry.size.w,
-                    };
-
-                    sizes.iter_mut().for_each(|len| {
-                        *len = (((*len as f64) / (previous_length as f64)) * (new_length as f64))
-                            .round() as i32;
-                    });
-                    let sum: i32 = sizes.iter().sum();
-                    if sum < new_length {
-                        *sizes.last_mut().unwrap() += new_length - sum;
-                    }
+                let data = tree.get_mut(&group).unwrap().data_mut();
+                if data.is_group() {
+                    let new_orientation = new_orientation.unwrap_or(!data.orientation());
+                    data.set_orientation(new_orientation, preserve_ratios);
+
+                    let blocker = TilingLayout::update_positions(
+                        &output,
+                        &mut tree,
+                        &self.config,
+                        self.geometry_hook.as_ref(),
+                        queue.overscan,
+                        queue.reserved_zone,
+                    );
+                    queue.push_tree(tree, self.config.animation_duration, blocker);
+                }
+            }
+        }
+    }
 
-                    *orientation = new_orientation;
+    pub fn transpose_output(&mut self, output: &Output) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let Some(root_id) = tree.root_node_id().cloned() else { return };
 
-                    let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-                    queue.push_tree(tree, ANIMATION_DURATION, blocker);
-                }
+        for node_id in tree
+            .traverse_pre_order_ids(&root_id)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .into_iter()
+        {
+            let data = tree.get_mut(&node_id).unwrap().data_mut();
+            if data.is_group() {
+                let orientation = data.orientation();
+                data.set_orientation(!orientation, false);
             }
         }
+
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
     }
 
-    pub fn toggle_stacking<'a>(&mut self, seat: &Seat<State>, mut focus_stack: FocusStackMut) {
+    pub fn toggle_stacking<'a>(
+        &mut self,
+        seat: &Seat<State>,
+        mut focus_stack: FocusStackMut,
+        order: StackOrder,
+    ) -> Option<KeyboardFocusTarget> {
+        self.record_current_focus(seat);
         let output = seat.active_output();
-        let Some(queue) = self.queues.get_mut(&output) else { return };
+        let queue = self.queues.get_mut(&output)?;
         let mut tree = queue.trees.back().unwrap().0.copy_clone();
 
-        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return };
-        if let Some((last_active, last_active_data)) =
-            TilingLayout::currently_focused_node(&tree, &seat.active_output(), target)
-        {
-            match last_active_data {
-                FocusedNodeData::Window(mapped) => {
-                    if mapped.is_window() {
-                        // if it is just a window
-                        match tree.get_mut(&last_active).unwrap().data_mut() {
-                            Data::Mapped { mapped, .. } => {
-                                mapped.convert_to_stack(std::iter::once((&output, mapped.bbox())));
-                                focus_stack.append(&mapped);
-                            }
-                            _ => unreachable!(),
-                        };
-                    } else {
-                        // if we have a stack
-                        let mut surfaces = mapped.windows().map(|(s, _)| s);
-                        let first = surfaces.next().expect("Stack without a window?");
-
-                        let handle = match tree.get_mut(&last_active).unwrap().data_mut() {
-                            Data::Mapped { mapped, .. } => {
-                                let handle = mapped.loop_handle();
-                                mapped.convert_to_surface(
-                                    first,
-                                    std::iter::once((&output, mapped.bbox())),
-                                );
-                                focus_stack.append(&mapped);
-                                handle
-                            }
-                            _ => unreachable!(),
-                        };
+        let target = seat.get_keyboard().unwrap().current_focus()?;
+        let (last_active, last_active_data) =
+            TilingLayout::currently_focused_node(&tree, &seat.active_output(), target)?;
 
-                        // map the rest
-                        for other in surfaces {
-                            other.try_force_undecorated(false);
-                            other.set_tiled(false);
-                            let window =
-                                CosmicMapped::from(CosmicWindow::new(other, handle.clone()));
-                            window.output_enter(&output, window.bbox());
-                            window.set_bounds(output.geometry().size);
+        let new_focus: KeyboardFocusTarget = match last_active_data {
+            FocusedNodeData::Window(mapped) => {
+                if mapped.is_window() {
+                    // if it is just a window
+                    match tree.get_mut(&last_active).unwrap().data_mut() {
+                        Data::Mapped { mapped, .. } => {
+                            stack_window(self.stack_factory.as_ref(), mapped, &output);
+                            focus_stack.append(&mapped);
+                            mapped.clone().into()
+                        }
+                        _ => unreachable!(),
+                    }
+                } else {
+                    // if we have a stack
+                    let mut surfaces = mapped.windows().map(|(s, _)| s);
+                    let first = surfaces.next().expect("Stack without a window?");
+                    let old_parent = tree.get(&last_active).unwrap().parent().cloned();
 
-                            TilingLayout::map_to_tree(
-                                &mut tree,
-                                window,
-                                &output,
-                                Some(focus_stack.iter()),
-                                None,
-                            )
+                    let (handle, window) = match tree.get_mut(&last_active).unwrap().data_mut() {
+                        Data::Mapped { mapped, .. } => {
+                            let handle = mapped.loop_handle();
+                            mapped.convert_to_surface(
+                                first,
+                                std::iter::once((&output, mapped.bbox())),
+                            );
+                            focus_stack.append(&mapped);
+                            (handle, mapped.clone())
                         }
+                        _ => unreachable!(),
+                    };
 
-                        // TODO: Focus the new group
+                    // map the rest
+                    for other in surfaces {
+                        other.try_force_undecorated(false);
+                        other.set_tiled(false);
+                        let window = CosmicMapped::from(CosmicWindow::new(other, handle.clone()));
+                        window.output_enter(&output, window.bbox());
+                        window.set_bounds(output.geometry().size);
+
+                        TilingLayout::map_to_tree(
+                            &mut tree,
+                            window,
+                            &output,
+                            Some(focus_stack.iter()),
+                            None,
+                            self.config.preferred_tile_aspect,
+                            None,
+                            self.config.new_window_fraction,
+                        );
                     }
-                }
-                FocusedNodeData::Group(_, _) => {
-                    let mut handle = None;
-                    let surfaces = tree
-                        .traverse_pre_order(&last_active)
+
+                    TilingLayout::flatten(&mut tree);
+                    // focus the new group, which is the top-most one we created
+                    let group_id = tree
+                        .ancestor_ids(&last_active)
                         .unwrap()
-                        .flat_map(|node| match node.data() {
-                            Data::Mapped { mapped, .. } => {
-                                if handle.is_none() {
-                                    handle = Some(mapped.loop_handle());
-                                }
-                                Some(mapped.windows().map(|(s, _)| s))
+                        .take_while(|id| Some(*id) != old_parent.as_ref())
+                        .last()
+                        .cloned();
+                    match group_id {
+                        Some(group_id) => {
+                            let mut group_focus_stack = vec![last_active.clone()];
+                            group_focus_stack.extend(
+                                tree.ancestor_ids(&last_active)
+                                    .unwrap()
+                                    .take_while(|id| **id != group_id)
+                                    .cloned(),
+                            );
+                            let alive = match tree.get(&group_id).unwrap().data() {
+                                Data::Group { alive, .. } => Arc::downgrade(alive),
+                                _ => unreachable!(),
+                            };
+                            WindowGroup {
+                                node: group_id,
+                                output: output.downgrade(),
+                                alive,
+                                focus_stack: group_focus_stack,
                             }
-                            Data::Group { .. } => None,
-                        })
-                        .flatten()
-                        .collect::<Vec<_>>();
-
-                    if surfaces.is_empty() {
-                        return;
+                            .into()
+                        }
+                        None => window.into(),
                     }
-                    let handle = handle.unwrap();
-                    let stack = CosmicStack::new(surfaces.into_iter(), handle);
+                }
+            }
+            FocusedNodeData::Group(_, _) => {
+                let mut handle = None;
+                let mut surfaces = tree
+                    .traverse_pre_order(&last_active)
+                    .unwrap()
+                    .flat_map(|node| match node.data() {
+                        Data::Mapped { mapped, .. } => {
+                            if handle.is_none() {
+                                handle = Some(mapped.loop_handle());
+                            }
+                            let rank = focus_stack
+                                .iter()
+                                .position(|m| m == mapped)
+                                .unwrap_or(usize::MAX);
+                            Some(mapped.windows().map(move |(s, _)| (s, rank)))
+                        }
+                        Data::Group { .. } => None,
+                    })
+                    .flatten()
+                    .collect::<Vec<_>>();
+                if order == StackOrder::FocusOrder {
+                    // stable, so unfocused windows keep their spatial order
+                    surfaces.sort_by_key(|(_, rank)| *rank);
+                }
+                let surfaces = surfaces.into_iter().map(|(s, _)| s).collect::<Vec<_>>();
 
-                    for child in tree
-                        .children_ids(&last_active)
-                        .unwrap()
-                        .cloned()
-                        .collect::<Vec<_>>()
-                        .into_iter()
-                    {
-                        tree.remove_node(child, RemoveBehavior::DropChildren)
-                            .unwrap();
+                if surfaces.is_empty() {
+                    return None;
+                }
+                let mapped = build_stack(self.stack_factory.as_ref(), surfaces, handle.unwrap());
+
+                for child in tree
+                    .children_ids(&last_active)
+                    .unwrap()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                {
+                    tree.remove_node(child, RemoveBehavior::DropChildren)
+                        .unwrap();
+                }
+                let data = tree.get_mut(&last_active).unwrap().data_mut();
+
+                let geo = *data.geometry();
+                mapped.set_geometry(geo);
+                mapped.output_enter(&output, mapped.bbox());
+                mapped.set_activated(true);
+                mapped.configure();
+                mapped.refresh();
+
+                *mapped.last_geometry.lock().unwrap() = Some(geo);
+                *mapped.tiling_node_id.lock().unwrap() = Some(last_active);
+                focus_stack.append(&mapped);
+                *data = Data::Mapped {
+                    mapped: mapped.clone(),
+                    last_geometry: geo,
+                    sticky_end: false,
+                    always_on_top: false,
+                };
+                mapped.into()
+            }
+        };
+
+        TilingLayout::flatten(&mut tree);
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+
+        Some(new_focus)
+    }
+
+    /// Replaces the whole tree of `output` with a single stack of all its windows
+    pub fn stack_all(
+        &mut self,
+        output: &Output,
+        seat: &Seat<State>,
+    ) -> Option<KeyboardFocusTarget> {
+        self.record_current_focus(seat);
+        let queue = self.queues.get_mut(output)?;
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let root_id = tree.root_node_id()?.clone();
+
+        let mut handle = None;
+        let surfaces = tree
+            .traverse_pre_order(&root_id)
+            .unwrap()
+            .flat_map(|node| match node.data() {
+                Data::Mapped { mapped, .. } => {
+                    if handle.is_none() {
+                        handle = Some(mapped.loop_handle());
                     }
-                    let data = tree.get_mut(&last_active).unwrap().data_mut();
+                    Some(mapped.windows().map(|(s, _)| s))
+                }
+                Data::Group { .. } => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        if surfaces.len() < 2 {
+            return None;
+        }
+        let mapped = build_stack(self.stack_factory.as_ref(), surfaces, handle.unwrap());
 
-                    let geo = *data.geometry();
-                    stack.set_geometry(geo);
-                    stack.output_enter(&output, stack.bbox());
-                    stack.set_activate(true);
-                    stack.active().send_configure();
-                    stack.refresh();
+        let geo = *tree.get(&root_id).unwrap().data().geometry();
+        tree.remove_node(root_id, RemoveBehavior::DropChildren)
+            .unwrap();
+        mapped.set_geometry(geo);
+        mapped.output_enter(output, mapped.bbox());
+        mapped.set_activated(true);
+        mapped.configure();
+        mapped.refresh();
+
+        *mapped.last_geometry.lock().unwrap() = Some(geo);
+        let node_id = tree
+            .insert(
+                Node::new(Data::Mapped {
+                    mapped: mapped.clone(),
+                    last_geometry: geo,
+                    sticky_end: false,
+                    always_on_top: false,
+                }),
+                InsertBehavior::AsRoot,
+            )
+            .unwrap();
+        *mapped.tiling_node_id.lock().unwrap() = Some(node_id);
 
-                    let mapped = CosmicMapped::from(stack);
-                    *mapped.last_geometry.lock().unwrap() = Some(geo);
-                    *mapped.tiling_node_id.lock().unwrap() = Some(last_active);
-                    focus_stack.append(&mapped);
-                    *data = Data::Mapped {
-                        mapped,
-                        last_geometry: geo,
-                    };
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+
+        Some(mapped.into())
+    }
+
+    /// Splits a stack filling the whole output back into evenly sized windows, see `stack_all`
+    pub fn unstack_all(&mut self, output: &Output) -> Option<KeyboardFocusTarget> {
+        let queue = self.queues.get_mut(output)?;
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let root_id = tree.root_node_id()?.clone();
+
+        let (handle, window, surfaces) = match tree.get_mut(&root_id).unwrap().data_mut() {
+            Data::Mapped { mapped, .. } if mapped.is_stack() => {
+                let mut surfaces = mapped.windows().map(|(s, _)| s).collect::<Vec<_>>();
+                let first = surfaces.remove(0);
+                let handle = mapped.loop_handle();
+                mapped.convert_to_surface(first, std::iter::once((output, mapped.bbox())));
+                (handle, mapped.clone(), surfaces)
+            }
+            _ => return None,
+        };
+
+        for other in surfaces {
+            other.try_force_undecorated(false);
+            other.set_tiled(false);
+            let window = CosmicMapped::from(CosmicWindow::new(other, handle.clone()));
+            window.output_enter(output, window.bbox());
+            window.set_bounds(output.geometry().size);
+
+            TilingLayout::map_to_columns(
+                &mut tree,
+                window,
+                None::<std::iter::Empty<&CosmicMapped>>,
+                None,
+                SizingPolicy::EqualLength,
+                self.config.min_size_on(output),
+            );
+        }
+
+        let blocker = TilingLayout::update_positions(
+            output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+
+        Some(window.into())
+    }
+
+    /// Re-flows the tree of `output`, `instant` skips the animation (e.g. for maximize transitions)
+    pub fn recalculate(&mut self, output: &Output, instant: bool) {
+        let Some(queue) = self.queues.get_mut(output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        let duration = if instant {
+            Duration::ZERO
+        } else {
+            self.config.animation_duration
+        };
+        queue.push_tree(tree, duration, blocker);
+    }
+
+    /// Reflows the layout of every output, e.g. after the config changed
+    pub fn recalculate_all(&mut self) {
+        for (output_data, queue) in self.queues.iter_mut() {
+            let mut tree = queue.trees.back().unwrap().0.copy_clone();
+            let blocker = TilingLayout::update_positions(
+                &output_data.output,
+                &mut tree,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                queue.overscan,
+                queue.reserved_zone,
+            );
+            queue.push_tree(tree, self.config.animation_duration, blocker);
+        }
+    }
+
+    /// To be called whenever layer-shell surfaces change the exclusive zone of `output`.
+    pub fn handle_layer_zone_change(&mut self, output: &Output) {
+        self.recalculate(output, false)
+    }
+
+    pub fn refresh(&mut self) {
+        #[cfg(feature = "debug")]
+        puffin::profile_function!();
+
+        self.reconcile();
+        self.sync_sticky();
+        if self.config.auto_balance {
+            self.auto_balance();
+        }
+        // catches broken trees close to the push that committed them
+        #[cfg(feature = "debug")]
+        if let Err(err) = self.validate() {
+            warn!(?err, "Tiling tree is inconsistent.");
+        }
+
+        for (_, mapped, _) in self.mapped() {
+            mapped.refresh();
+        }
+    }
+
+    // nudges lopsided groups a step towards even sizes, called every refresh until they are usable again
+    fn auto_balance(&mut self) {
+        for (output_data, queue) in self.queues.iter_mut() {
+            if queue.animation_start.is_some() || queue.trees.len() > 1 {
+                continue;
+            }
+            let mut tree = queue.trees.back().unwrap().0.copy_clone();
+            let Some(root_id) = tree.root_node_id().cloned() else { continue };
+
+            let mut changed = false;
+            for node_id in tree
+                .traverse_pre_order_ids(&root_id)
+                .unwrap()
+                .collect::<Vec<_>>()
+                .into_iter()
+            {
+                if let Data::Group { sizes, locked, .. } =
+                    tree.get_mut(&node_id).unwrap().data_mut()
+                {
+                    if locked.iter().any(|locked| *locked) {
+                        continue;
+                    }
+                    let total = sizes.iter().sum::<i32>();
+                    let even = total as f64 / sizes.len() as f64;
+                    // with many children not even an even split reaches the threshold
+                    let threshold =
+                        ((total as f64 * AUTO_BALANCE_THRESHOLD) as i32).min(even as i32);
+                    if sizes.iter().all(|size| *size >= threshold) {
+                        continue;
+                    }
+
+                    let previous = sizes.clone();
+                    for size in sizes.iter_mut() {
+                        let step = ((even - *size as f64) * AUTO_BALANCE_STEP).round() as i32;
+                        // always make progress, so the correction terminates
+                        *size += if step == 0 {
+                            (even as i32 - *size).signum()
+                        } else {
+                            step
+                        };
+                    }
+                    let missing = total - sizes.iter().sum::<i32>();
+                    *sizes.last_mut().unwrap() += missing;
+                    changed |= *sizes != previous;
                 }
             }
 
-            let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-            queue.push_tree(tree, ANIMATION_DURATION, blocker);
+            if changed {
+                let blocker = TilingLayout::update_positions(
+                    &output_data.output,
+                    &mut tree,
+                    &self.config,
+                    self.geometry_hook.as_ref(),
+                    queue.overscan,
+                    queue.reserved_zone,
+                );
+                queue.push_tree(tree, Duration::ZERO, blocker);
+            }
         }
     }
 
-    pub fn recalculate(&mut self, output: &Output) {
-        let Some(queue) = self.queues.get_mut(output) else { return };
-        let mut tree = queue.trees.back().unwrap().0.copy_clone();
-        let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-        queue.push_tree(tree, ANIMATION_DURATION, blocker);
-    }
+    pub fn reconcile(&mut self) {
+        let mut dead_windows = Vec::new();
+        for queue in self.queues.values() {
+            let tree = &queue.trees.back().unwrap().0;
+            let Some(root_id) = tree.root_node_id() else { continue };
 
-    pub fn refresh(&mut self) {
-        #[cfg(feature = "debug")]
-        puffin::profile_function!();
+            for node_id in tree.traverse_pre_order_ids(root_id).unwrap() {
+                if let Data::Mapped { mapped, .. } = tree.get(&node_id).unwrap().data() {
+                    // mirrors keep pointing at the window's own node
+                    if self.sticky_windows.contains(mapped)
+                        && TilingLayout::is_sticky_mirror(tree, mapped)
+                    {
+                        continue;
+                    }
+                    // clients may re-create surfaces, which can leave a stale id behind
+                    let mut tiling_node_id = mapped.tiling_node_id.lock().unwrap();
+                    if tiling_node_id.as_ref() != Some(&node_id) {
+                        warn!(?mapped, ?node_id, stale = ?*tiling_node_id, "Fixing orphaned tiling node id.");
+                        *tiling_node_id = Some(node_id.clone());
+                    }
+                    std::mem::drop(tiling_node_id);
 
-        let dead_windows = self
-            .mapped()
-            .map(|(_, w, _)| w.clone())
-            .filter(|w| !w.alive())
-            .collect::<Vec<_>>();
-        for dead_window in dead_windows.iter() {
-            self.unmap_window_internal(&dead_window);
+                    if !mapped.alive() {
+                        dead_windows.push(mapped.clone());
+                    }
+                }
+            }
         }
 
-        for (_, mapped, _) in self.mapped() {
-            mapped.refresh();
+        for dead_window in dead_windows.iter() {
+            self.unmap_window_internal(&dead_window);
         }
     }
 
     pub fn animations_going(&self) -> bool {
-        self.queues
-            .values()
-            .any(|queue| queue.animation_start.is_some())
+        self.queues.values().any(|queue| {
+            queue.animation_start.is_some()
+                // keep frames coming, until a debounced animation starts
+                || (!self.config.animation_delay.is_zero() && queue.trees.len() > 1)
+                || match (
+                    self.config.focus_pulse_duration,
+                    queue.focus_pulse.lock().unwrap().as_ref(),
+                ) {
+                    (Some(duration), Some((_, start))) => start.elapsed() < duration,
+                    _ => false,
+                }
+        })
+    }
+
+    /// Time left until the running animation of `output` finishes
+    pub fn animation_remaining(&self, output: &Output) -> Option<Duration> {
+        let queue = self.queues.get(output)?;
+        let start = queue.animation_start?;
+        let duration = queue.trees.get(1)?.1;
+        Some(duration.saturating_sub(start.elapsed()))
+    }
+
+    /// Number of blockers still waiting on clients, across all outputs
+    pub fn outstanding_blocker_count(&self) -> usize {
+        self.pending_blockers
+            .iter()
+            .chain(
+                self.queues
+                    .values()
+                    .flat_map(|queue| queue.trees.iter().flat_map(|(_, _, blocker)| blocker)),
+            )
+            .filter(|blocker| !blocker.is_signaled())
+            .count()
     }
 
     pub fn update_animation_state(&mut self) -> HashMap<ClientId, Client> {
@@ -1374,11 +5700,41 @@ impl TilingLayout {
                     continue;
                 }
             }
+            let delay = self.config.animation_delay;
+            if !delay.is_zero() && queue.trees.len() > 1 {
+                // instant changes like interactive resizes are never held back
+                let animated = !queue.trees.back().unwrap().1.is_zero();
+                if animated
+                    && queue
+                        .pending_since
+                        .map(|since| since.elapsed() < delay)
+                        .unwrap_or(false)
+                {
+                    continue;
+                }
+                while queue.trees.len() > 2 {
+                    let (_, _, blocker) = queue.trees.remove(1).unwrap();
+                    if let Some(blocker) = blocker {
+                        clients.extend(blocker.signal_ready());
+                    }
+                }
+            }
             if let Some((_, _, blocker)) = queue.trees.get(1) {
                 if let Some(blocker) = blocker {
                     if blocker.is_ready() && blocker.is_signaled() {
                         clients.extend(blocker.signal_ready());
                         queue.animation_start = Some(Instant::now());
+                    } else if blocker.elapsed() >= self.config.blocker_timeout {
+                        let app_ids = blocker
+                            .unacked()
+                            .map(|surface| surface.app_id())
+                            .collect::<Vec<_>>();
+                        warn!(
+                            ?app_ids,
+                            "Configure not acked in time, continuing without it."
+                        );
+                        clients.extend(blocker.signal_ready());
+                        queue.animation_start = Some(Instant::now());
                     }
                 } else {
                     queue.animation_start = Some(Instant::now());
@@ -1389,6 +5745,47 @@ impl TilingLayout {
         clients
     }
 
+    /// Like `update_animation_state`, but skips animations, which would not
+    /// get at least `min_frames` frames at the observed `frame_interval`.
+    pub fn update_animation_state_adaptive(
+        &mut self,
+        frame_interval: Duration,
+        min_frames: u32,
+    ) -> HashMap<ClientId, Client> {
+        for queue in self.queues.values_mut() {
+            if queue.animation_start.is_none() {
+                continue;
+            }
+            let duration = queue
+                .trees
+                .get(1)
+                .expect("Animation going without second tree?")
+                .1;
+            if duration < frame_interval * min_frames {
+                // jump to the final tree
+                let _ = queue.animation_start.take();
+                let _ = queue.trees.pop_front();
+                let _ = queue.trees.front_mut().unwrap().2.take();
+            }
+        }
+
+        self.update_animation_state()
+    }
+
+    pub fn window_resize_edges(&self, window: &CosmicMapped) -> ResizeEdge {
+        let Some(node_id) = window.tiling_node_id.lock().unwrap().clone() else { return ResizeEdge::empty() };
+        self.queues
+            .values()
+            .map(|queue| &queue.trees.back().unwrap().0)
+            .find(|tree| {
+                tree.get(&node_id)
+                    .map(|node| node.data().is_mapped(Some(window)))
+                    .unwrap_or(false)
+            })
+            .map(|tree| TilingLayout::possible_resizes(tree, node_id))
+            .unwrap_or(ResizeEdge::empty())
+    }
+
     pub fn possible_resizes(tree: &Tree<Data>, mut node_id: NodeId) -> ResizeEdge {
         let mut edges = ResizeEdge::empty();
 
@@ -1430,6 +5827,19 @@ impl TilingLayout {
         edges: ResizeEdge,
         amount: i32,
     ) -> bool {
+        self.resize_applied(focused, direction, edges, amount)
+            .is_some()
+    }
+
+    /// Like `resize`, but tells if the full `amount` was applied.
+    /// `None` if `focused` isn't tiled, `Some(false)` if the resize was clamped or nothing could be resized.
+    pub fn resize_applied(
+        &mut self,
+        focused: &KeyboardFocusTarget,
+        direction: ResizeDirection,
+        edges: ResizeEdge,
+        amount: i32,
+    ) -> Option<bool> {
         let Some((output, mut node_id)) = self.queues.iter().find_map(|(output, queue)| {
             let tree = &queue.trees.back().unwrap().0;
             let root_id = tree.root_node_id()?;
@@ -1442,7 +5852,7 @@ impl TilingLayout {
                 _ => None,
             };
             id.map(|id| (output.output.clone(), id))
-        }) else { return false };
+        }) else { return None };
 
         let queue = self.queues.get_mut(&output).unwrap();
         let mut tree = queue.trees.back().unwrap().0.copy_clone();
@@ -1481,34 +5891,155 @@ impl TilingLayout {
                         (other_idx, node_idx)
                     };
 
-                    if sizes[shrink_idx] + sizes[grow_idx]
-                        < match orientation {
-                            Orientation::Vertical => 720,
-                            Orientation::Horizontal => 480,
-                        }
-                    {
-                        return true;
+                    let min_length = self.config.min_length_on(orientation, &output);
+                    let headroom = (sizes[shrink_idx] - min_length).max(0);
+                    if sizes[shrink_idx] + sizes[grow_idx] < min_length * 2 || headroom == 0 {
+                        return Some(false);
                     };
 
-                    let old_size = sizes[shrink_idx];
-                    sizes[shrink_idx] =
-                        (old_size - amount).max(if orientation == Orientation::Vertical {
-                            360
-                        } else {
-                            240
-                        });
-                    let diff = old_size - sizes[shrink_idx];
-                    sizes[grow_idx] += diff;
+                    let applied = amount.min(headroom);
+                    sizes[shrink_idx] -= applied;
+                    sizes[grow_idx] += applied;
+                    let blocker = TilingLayout::update_positions(
+                        &output,
+                        &mut tree,
+                        &self.config,
+                        self.geometry_hook.as_ref(),
+                        queue.overscan,
+                        queue.reserved_zone,
+                    );
+                    queue.push_tree(tree, Duration::ZERO, blocker);
+
+                    return Some(applied == amount);
                 }
                 _ => unreachable!(),
             }
-            let blocker = TilingLayout::update_positions(&output, &mut tree, self.gaps);
-            queue.push_tree(tree, Duration::ZERO, blocker);
+        }
+
+        Some(false)
+    }
 
-            return true;
+    pub fn grow_focused(&mut self, seat: &Seat<State>, fraction: f64) {
+        self.resize_focused(seat, Some(fraction))
+    }
+
+    pub fn shrink_focused(&mut self, seat: &Seat<State>, fraction: f64) {
+        self.resize_focused(seat, Some(-fraction))
+    }
+
+    /// Distributes the space of the focused node's group evenly again
+    pub fn reset_focused_size(&mut self, seat: &Seat<State>) {
+        self.resize_focused(seat, None)
+    }
+
+    fn resize_focused(&mut self, seat: &Seat<State>, fraction: Option<f64>) {
+        let output = seat.active_output();
+        let Some(queue) = self.queues.get_mut(&output) else { return };
+        let mut tree = queue.trees.back().unwrap().0.copy_clone();
+
+        let Some(target) = seat.get_keyboard().unwrap().current_focus() else { return };
+        let Some((node_id, _)) = TilingLayout::currently_focused_node(&tree, &output, target) else { return };
+        let Some(group_id) = tree.get(&node_id).unwrap().parent().cloned() else { return };
+        let idx = tree
+            .children_ids(&group_id)
+            .unwrap()
+            .position(|id| id == &node_id)
+            .unwrap();
+
+        match tree.get_mut(&group_id).unwrap().data_mut() {
+            Data::Group {
+                sizes, orientation, ..
+            } => {
+                let min = self.config.min_length_on(*orientation, &output);
+                let len = sizes.len() as i32;
+                let total: i32 = sizes.iter().sum();
+
+                match fraction {
+                    Some(fraction) => {
+                        let max = total - min * (len - 1);
+                        if max < min {
+                            return;
+                        }
+                        let others = total - sizes[idx];
+                        let new_size =
+                            (sizes[idx] + (fraction * total as f64).round() as i32).clamp(min, max);
+                        let remaining = total - new_size;
+
+                        // shrink or grow every sibling proportionally
+                        for (i, size) in sizes.iter_mut().enumerate() {
+                            if i != idx {
+                                *size = if others > 0 {
+                                    ((*size as f64 / others as f64) * remaining as f64).round()
+                                        as i32
+                                } else {
+                                    remaining / (len - 1)
+                                }
+                                .max(min);
+                            }
+                        }
+                        let siblings: i32 = sizes
+                            .iter()
+                            .enumerate()
+                            .filter(|(i, _)| *i != idx)
+                            .map(|(_, size)| *size)
+                            .sum();
+                        sizes[idx] = total - siblings;
+                    }
+                    None => {
+                        for (i, size) in sizes.iter_mut().enumerate() {
+                            *size = total / len + if (i as i32) < total % len { 1 } else { 0 };
+                        }
+                    }
+                }
+            }
+            _ => unreachable!(),
         }
 
-        true
+        let blocker = TilingLayout::update_positions(
+            &output,
+            &mut tree,
+            &self.config,
+            self.geometry_hook.as_ref(),
+            queue.overscan,
+            queue.reserved_zone,
+        );
+        queue.push_tree(tree, self.config.animation_duration, blocker);
+    }
+
+    pub fn resize_accelerated(
+        &mut self,
+        seat: &Seat<State>,
+        direction: ResizeDirection,
+        edges: ResizeEdge,
+    ) -> bool {
+        let Some(focused) = seat.get_keyboard().unwrap().current_focus() else { return false };
+
+        let now = Instant::now();
+        let accel = self
+            .resize_accel
+            .entry(seat.clone())
+            .or_insert_with(|| ResizeAccel {
+                last_resize: now,
+                velocity: RESIZE_BASE_STEP,
+            });
+        // decay towards the base step the longer no resize happened, then accelerate
+        let decay = (now.duration_since(accel.last_resize).as_secs_f64()
+            / RESIZE_ACCEL_TIMEOUT.as_secs_f64())
+        .min(1.0);
+        accel.velocity = ((accel.velocity * (1.0 - decay) + RESIZE_BASE_STEP * decay)
+            * RESIZE_ACCELERATION)
+            .clamp(RESIZE_BASE_STEP, RESIZE_MAX_STEP);
+        accel.last_resize = now;
+        let amount = accel.velocity.round() as i32;
+
+        let applied = self.resize_applied(&focused, direction, edges, amount);
+        if applied == Some(false) {
+            // the edge is maxed out, start over once it can move again
+            if let Some(accel) = self.resize_accel.get_mut(seat) {
+                accel.velocity = RESIZE_BASE_STEP;
+            }
+        }
+        applied.is_some()
     }
 
     fn last_active_window<'a>(
@@ -1617,23 +6148,368 @@ impl TilingLayout {
         Ok(group_id)
     }
 
+    // gives `new_id` `fraction` of a freshly created two child group
+    fn split_for_new_window(
+        tree: &mut Tree<Data>,
+        group_id: &NodeId,
+        new_id: &NodeId,
+        fraction: f64,
+    ) {
+        let new_idx = tree
+            .children_ids(group_id)
+            .unwrap()
+            .position(|id| id == new_id)
+            .unwrap();
+        if let Data::Group { sizes, .. } = tree.get_mut(group_id).unwrap().data_mut() {
+            let total = sizes.iter().sum::<i32>();
+            let new_length = TilingLayout::new_window_length(total, fraction);
+            sizes[new_idx] = new_length;
+            sizes[1 - new_idx] = total - new_length;
+        }
+    }
+
+    // length of the new window when splitting `total` for it
+    fn new_window_length(total: i32, fraction: f64) -> i32 {
+        (total as f64 * fraction.clamp(0.05, 0.95)).round() as i32
+    }
+
+    // Updates the sizes of `group_id` for the child already placed at `idx`
+    fn add_to_group(
+        tree: &mut Tree<Data>,
+        group_id: &NodeId,
+        idx: usize,
+        policy: SizingPolicy,
+        min_size: Size<i32, Logical>,
+    ) {
+        let weights = match policy {
+            SizingPolicy::EqualLength => None,
+            SizingPolicy::EqualArea => {
+                let mut counts = tree
+                    .children_ids(group_id)
+                    .unwrap()
+                    .map(|child_id| {
+                        tree.traverse_pre_order(child_id)
+                            .unwrap()
+                            .filter(|node| node.data().is_mapped(None))
+                            .count()
+                    })
+                    .collect::<Vec<_>>();
+                let new = counts.remove(idx);
+                Some((counts, new))
+            }
+        };
+        let data = tree.get_mut(group_id).unwrap().data_mut();
+        let min_length = match data.orientation() {
+            Orientation::Vertical => min_size.w,
+            Orientation::Horizontal => min_size.h,
+        };
+        data.add_window(
+            idx,
+            weights.as_ref().map(|(counts, new)| (&counts[..], *new)),
+            min_length,
+        );
+    }
+
+    // `added` is the window mapped since the last reflow, if any.
+    // Returns the quadrant it was stacked into, if it didn't get its own.
+    fn reflow_quadrants(
+        tree: &mut Tree<Data>,
+        output: &Output,
+        factory: Option<&StackFactory>,
+        added: Option<&CosmicMapped>,
+    ) -> Option<CosmicMapped> {
+        let tiles = |tree: &Tree<Data>| {
+            tree.root_node_id()
+                .into_iter()
+                .flat_map(|root_id| tree.traverse_pre_order_ids(root_id).unwrap())
+                .filter_map(|id| match tree.get(&id).unwrap().data() {
+                    Data::Mapped { mapped, .. } => Some((id, mapped.clone())),
+                    Data::Group { .. } => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut windows = tiles(tree);
+        if windows.is_empty() {
+            return None;
+        }
+
+        // once all quadrants are taken, only the new window has to find its place
+        let added_id = added
+            .and_then(|added| added.tiling_node_id.lock().unwrap().clone())
+            .filter(|node_id| windows.len() > 4 && windows.iter().any(|(id, _)| id == node_id));
+        let (quadrants, extra) = if let Some(added_id) = added_id {
+            let (_, added) =
+                windows.remove(windows.iter().position(|(id, _)| id == &added_id).unwrap());
+            TilingLayout::remove_from_tree(tree, added_id);
+            (tiles(tree), vec![added])
+        } else {
+            let extra = windows
+                .split_off(windows.len().min(4))
+                .into_iter()
+                .map(|(_, mapped)| mapped)
+                .collect::<Vec<_>>();
+            let root_id = tree.root_node_id().cloned().unwrap();
+            tree.remove_node(root_id, RemoveBehavior::DropChildren)
+                .unwrap();
+
+            // one row per two windows, stacked on top of each other
+            let geo = layer_map_for_output(output).non_exclusive_zone();
+            let root_id = (windows.len() > 2).then(|| {
+                tree.insert(
+                    Node::new(Data::new_group(Orientation::Horizontal, geo)),
+                    InsertBehavior::AsRoot,
+                )
+                .unwrap()
+            });
+            let mut quadrants = Vec::new();
+            for row in windows.chunks(2) {
+                let row_id = if row.len() == 2 {
+                    Some(
+                        tree.insert(
+                            Node::new(Data::new_group(Orientation::Vertical, geo)),
+                            match root_id.as_ref() {
+                                Some(root_id) => InsertBehavior::UnderNode(root_id),
+                                None => InsertBehavior::AsRoot,
+                            },
+                        )
+                        .unwrap(),
+                    )
+                } else {
+                    root_id.clone()
+                };
+
+                for (_, mapped) in row {
+                    let node_id = tree
+                        .insert(
+                            Node::new(Data::Mapped {
+                                mapped: mapped.clone(),
+                                last_geometry: Rectangle::from_loc_and_size((0, 0), (100, 100)),
+                                sticky_end: false,
+                                always_on_top: false,
+                            }),
+                            match row_id.as_ref() {
+                                Some(row_id) => InsertBehavior::UnderNode(row_id),
+                                None => InsertBehavior::AsRoot,
+                            },
+                        )
+                        .unwrap();
+                    *mapped.tiling_node_id.lock().unwrap() = Some(node_id.clone());
+                    quadrants.push((node_id, mapped.clone()));
+                }
+            }
+            (quadrants, extra)
+        };
+
+        // all quadrants have the same size, so fill up the least crowded one
+        let mut quadrants = quadrants
+            .into_iter()
+            .map(|(node_id, mapped)| (node_id, mapped.windows().count()))
+            .collect::<Vec<_>>();
+        let mut holder = None;
+        for mapped in extra {
+            let (node_id, count) = quadrants
+                .iter_mut()
+                .min_by_key(|(_, count)| *count)
+                .unwrap();
+            *count += mapped.windows().count();
+
+            match tree.get_mut(node_id).unwrap().data_mut() {
+                Data::Mapped {
+                    mapped: quadrant, ..
+                } => {
+                    if quadrant.is_window() {
+                        stack_window(factory, quadrant, output);
+                    }
+                    let stack = quadrant.stack_ref_mut().unwrap();
+                    for (surface, _) in mapped.windows() {
+                        stack.add_window(surface, None);
+                    }
+                    if added == Some(&mapped) {
+                        holder = Some(quadrant.clone());
+                    }
+                }
+                _ => unreachable!(),
+            }
+            *mapped.tiling_node_id.lock().unwrap() = None;
+        }
+        holder
+    }
+
+    fn flatten(tree: &mut Tree<Data>) {
+        let Some(root_id) = tree.root_node_id().cloned() else { return };
+
+        for node_id in tree
+            .traverse_pre_order_ids(&root_id)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .into_iter()
+        {
+            let node = tree.get(&node_id).unwrap();
+            if node.data().is_group() && node.data().len() == 1 {
+                // RemoveBehavior::LiftChildren sadly does not what we want: lifting them into the same place.
+                // So we need to fix that manually..
+                let idx = node.parent().cloned().map(|parent_id| {
+                    tree.children_ids(&parent_id)
+                        .unwrap()
+                        .position(|id| id == &node_id)
+                        .unwrap()
+                });
+                let child_id = tree
+                    .children_ids(&node_id)
+                    .unwrap()
+                    .cloned()
+                    .next()
+                    .unwrap();
+                tree.remove_node(node_id, RemoveBehavior::LiftChildren)
+                    .unwrap();
+                if let Some(idx) = idx {
+                    tree.make_nth_sibling(&child_id, idx).unwrap();
+                } else {
+                    // additionally `RemoveBehavior::LiftChildren` doesn't work, when removing the root-node,
+                    // even with just one child. *sigh*
+                    tree.move_node(&child_id, MoveBehavior::ToRoot).unwrap();
+                }
+            }
+        }
+
+        TilingLayout::keep_sticky_last(tree);
+    }
+
+    fn keep_sticky_last(tree: &mut Tree<Data>) {
+        let Some(root_id) = tree.root_node_id().cloned() else { return };
+
+        for node_id in tree
+            .traverse_pre_order_ids(&root_id)
+            .unwrap()
+            .collect::<Vec<_>>()
+            .into_iter()
+        {
+            let sticky = tree
+                .children(&node_id)
+                .unwrap()
+                .zip(tree.children_ids(&node_id).unwrap())
+                .filter(|(child, _)| {
+                    matches!(
+                        child.data(),
+                        Data::Mapped {
+                            sticky_end: true,
+                            ..
+                        }
+                    )
+                })
+                .map(|(_, id)| id.clone())
+                .collect::<Vec<_>>();
+
+            for child_id in sticky {
+                let idx = tree
+                    .children_ids(&node_id)
+                    .unwrap()
+                    .position(|id| id == &child_id)
+                    .unwrap();
+                tree.make_last_sibling(&child_id).unwrap();
+                if let Data::Group { sizes, locked, .. } =
+                    tree.get_mut(&node_id).unwrap().data_mut()
+                {
+                    let size = sizes.remove(idx);
+                    sizes.push(size);
+                    let size_locked = locked.remove(idx);
+                    locked.push(size_locked);
+                }
+            }
+        }
+    }
+
+    fn tiling_area(
+        output: &Output,
+        config: &TilingConfig,
+        overscan: (i32, i32, i32, i32),
+        reserved_zone: Option<(Direction, f64)>,
+    ) -> Rectangle<i32, Logical> {
+        let mut geo = TilingLayout::usable_area(output, config, overscan);
+        if let Some((edge, fraction)) = reserved_zone {
+            let zone = TilingLayout::zone_geometry(geo, edge, fraction);
+            match edge {
+                Direction::Left => geo.loc.x += zone.size.w,
+                Direction::Up => geo.loc.y += zone.size.h,
+                Direction::Right | Direction::Down => {}
+            }
+            match edge {
+                Direction::Left | Direction::Right => geo.size.w -= zone.size.w,
+                Direction::Up | Direction::Down => geo.size.h -= zone.size.h,
+            }
+        }
+        geo
+    }
+
+    // `fraction` of `area` along `edge`
+    fn zone_geometry(
+        mut area: Rectangle<i32, Logical>,
+        edge: Direction,
+        fraction: f64,
+    ) -> Rectangle<i32, Logical> {
+        match edge {
+            Direction::Left | Direction::Right => {
+                let width = (area.size.w as f64 * fraction).round() as i32;
+                if edge == Direction::Right {
+                    area.loc.x += area.size.w - width;
+                }
+                area.size.w = width;
+            }
+            Direction::Up | Direction::Down => {
+                let height = (area.size.h as f64 * fraction).round() as i32;
+                if edge == Direction::Down {
+                    area.loc.y += area.size.h - height;
+                }
+                area.size.h = height;
+            }
+        }
+        area
+    }
+
+    fn usable_area(
+        output: &Output,
+        config: &TilingConfig,
+        overscan: (i32, i32, i32, i32),
+    ) -> Rectangle<i32, Logical> {
+        let outer = config.gaps.0.resolve(output.geometry().size);
+        let mut geo = layer_map_for_output(&output).non_exclusive_zone();
+        let (top, right, bottom, left) = overscan;
+        geo.loc.x += left;
+        geo.loc.y += top;
+        geo.size.w -= left + right;
+        geo.size.h -= top + bottom;
+        geo.loc.x += outer;
+        geo.loc.y += outer;
+        geo.size.w -= outer * 2;
+        geo.size.h -= outer * 2;
+        geo
+    }
+
     fn update_positions(
         output: &Output,
         tree: &mut Tree<Data>,
-        gaps: (i32, i32),
+        config: &TilingConfig,
+        geometry_hook: Option<&GeometryHook>,
+        overscan: (i32, i32, i32, i32),
+        reserved_zone: Option<(Direction, f64)>,
     ) -> Option<TilingBlocker> {
         #[cfg(feature = "debug")]
         puffin::profile_function!();
 
+        // not every path flattens eagerly, single child groups must never be laid out
+        TilingLayout::flatten(tree);
+
         if let Some(root_id) = tree.root_node_id() {
             let mut configures = Vec::new();
 
-            let (outer, inner) = gaps;
-            let mut geo = layer_map_for_output(&output).non_exclusive_zone();
-            geo.loc.x += outer;
-            geo.loc.y += outer;
-            geo.size.w -= outer * 2;
-            geo.size.h -= outer * 2;
+            let inner = config.gaps.1.resolve(output.geometry().size);
+            let mut geo = TilingLayout::tiling_area(output, config, overscan, reserved_zone);
+            if let Some(max_width) = config.single_window_max_width {
+                if tree.get(root_id).unwrap().data().is_mapped(None) && geo.size.w > max_width {
+                    geo.loc.x += (geo.size.w - max_width) / 2;
+                    geo.size.w = max_width;
+                }
+            }
             let mut stack = vec![geo];
 
             for node_id in tree
@@ -1642,44 +6518,31 @@ impl TilingLayout {
                 .collect::<Vec<_>>()
                 .into_iter()
             {
-                let node = tree.get_mut(&node_id).unwrap();
-                let data = node.data_mut();
-
-                // flatten tree
-                if data.is_group() && data.len() == 1 {
-                    // RemoveBehavior::LiftChildren sadly does not what we want: lifting them into the same place.
-                    // So we need to fix that manually..
-                    let idx = node.parent().cloned().map(|parent_id| {
-                        tree.children_ids(&parent_id)
-                            .unwrap()
-                            .position(|id| id == &node_id)
-                            .unwrap()
-                    });
-                    let child_id = tree
-                        .children_ids(&node_id)
-                        .unwrap()
-                        .cloned()
-                        .next()
-                        .unwrap();
-                    tree.remove_node(node_id, RemoveBehavior::LiftChildren)
-                        .unwrap();
-                    if let Some(idx) = idx {
-                        tree.make_nth_sibling(&child_id, idx).unwrap();
-                    } else {
-                        // additionally `RemoveBehavior::LiftChildren` doesn't work, when removing the root-node,
-                        // even with just one child. *sigh*
-                        tree.move_node(&child_id, MoveBehavior::ToRoot).unwrap();
-                    }
-
-                    continue;
-                }
-
                 if let Some(mut geo) = stack.pop() {
+                    let gap = tree
+                        .get(&node_id)
+                        .unwrap()
+                        .parent()
+                        .and_then(|parent_id| match tree.get(parent_id).unwrap().data() {
+                            Data::Group { gap_override, .. } => *gap_override,
+                            Data::Mapped { .. } => None,
+                        })
+                        .unwrap_or(inner);
+                    // mirrors are sized by the window's own output
+                    let mirror = match tree.get(&node_id).unwrap().data() {
+                        Data::Mapped { mapped, .. } => TilingLayout::is_sticky_mirror(tree, mapped),
+                        Data::Group { .. } => false,
+                    };
                     let node = tree.get_mut(&node_id).unwrap();
                     let data = node.data_mut();
                     if data.is_mapped(None) {
-                        geo.loc += (inner, inner).into();
-                        geo.size -= (inner * 2, inner * 2).into();
+                        geo.loc += (gap, gap).into();
+                        geo.size -= (gap * 2, gap * 2).into();
+                    }
+                    if geo.size.w < 1 || geo.size.h < 1 {
+                        warn!(?node_id, size = ?geo.size, "Clamping degenerate tiling slot");
+                        geo.size.w = geo.size.w.max(1);
+                        geo.size.h = geo.size.h.max(1);
                     }
                     data.update_geometry(geo);
 
@@ -1709,12 +6572,16 @@ impl TilingLayout {
                             }
                         },
                         Data::Mapped { mapped, .. } => {
-                            if !(mapped.is_fullscreen(true) || mapped.is_maximized(true)) {
+                            if !mirror && !(mapped.is_fullscreen(true) || mapped.is_maximized(true))
+                            {
                                 mapped.set_tiled(true);
-                                let internal_geometry = Rectangle::from_loc_and_size(
+                                let mut internal_geometry = Rectangle::from_loc_and_size(
                                     geo.loc + output.geometry().loc,
                                     geo.size,
                                 );
+                                if let Some(hook) = geometry_hook {
+                                    internal_geometry = (hook.0)(mapped, internal_geometry);
+                                }
                                 if mapped.geometry() != internal_geometry {
                                     mapped.set_geometry(internal_geometry);
                                     if let Some(serial) = mapped.configure() {
@@ -1741,16 +6608,88 @@ impl TilingLayout {
         None
     }
 
+    pub fn snapshot(&self, output: &Output) -> Snapshot {
+        let Some(queue) = self.queues.get(output) else { return Snapshot::default() };
+        let tree = &queue.trees.back().unwrap().0;
+        let Some(root) = tree.root_node_id() else { return Snapshot::default() };
+
+        Snapshot(
+            tree.traverse_pre_order_ids(root)
+                .unwrap()
+                .map(|id| {
+                    let geo = *tree.get(&id).unwrap().data().geometry();
+                    (id, geo)
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns all nodes that were added, moved or resized since `previous` was taken,
+    /// including nodes that have since been removed.
+    pub fn changed_nodes_since(&self, output: &Output, previous: &Snapshot) -> Vec<NodeId> {
+        let current = self.snapshot(output);
+
+        let mut changed = current
+            .0
+            .iter()
+            .filter(|(id, geo)| previous.0.get(*id) != Some(*geo))
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        changed.extend(
+            previous
+                .0
+                .keys()
+                .filter(|id| !current.0.contains_key(*id))
+                .cloned(),
+        );
+        changed
+    }
+
     pub fn element_under(
         &self,
         location: Point<f64, Logical>,
     ) -> Option<(PointerFocusTarget, Point<i32, Logical>)> {
         self.queues.iter().find_map(|(output_data, queue)| {
-            let tree = &queue.trees.back().unwrap().0;
+            let visible_tree;
+            let tree = match self.config.query_mode {
+                QueryMode::Target => &queue.trees.back().unwrap().0,
+                QueryMode::Visible => {
+                    visible_tree = queue.visible_tree(self.config.animation_curve);
+                    &visible_tree
+                }
+            };
             let root = tree.root_node_id()?;
-            let location = (location - output_data.location.to_f64()).to_i32_round();
+            let mut location = location - output_data.location.to_f64();
+            if let Some(transform) = queue.overview_transform {
+                location = transform.invert(location);
+            }
+            let location = location.to_i32_round();
+
+            let mut last_fork_hit = queue.last_fork_hit.lock().unwrap();
+            if let Some(target) = last_fork_hit.clone() {
+                let band = TilingLayout::fork_band(tree, &target).map(|mut band| {
+                    let hysteresis = self.config.fork_hysteresis;
+                    match target.orientation {
+                        Orientation::Vertical => {
+                            band.loc.x -= hysteresis;
+                            band.size.w += hysteresis * 2;
+                        }
+                        Orientation::Horizontal => {
+                            band.loc.y -= hysteresis;
+                            band.size.h += hysteresis * 2;
+                        }
+                    }
+                    band
+                });
+                if band.map(|band| band.contains(location)).unwrap_or(false) {
+                    return Some(TilingLayout::fork_focus(tree, target, output_data));
+                }
+                *last_fork_hit = None;
+            }
 
             let mut result = None;
+            // group whose child under the pointer was passed through
+            let mut passed_through = None;
             let mut lookup = Some(root.clone());
             while let Some(node) = lookup {
                 let data = tree.get(&node).unwrap().data();
@@ -1761,13 +6700,18 @@ impl TilingLayout {
                 lookup = None;
                 if result.is_some() && data.is_group() {
                     for child_id in tree.children_ids(&node).unwrap() {
-                        if tree
-                            .get(child_id)
-                            .unwrap()
-                            .data()
-                            .geometry()
-                            .contains(location)
-                        {
+                        let child = tree.get(child_id).unwrap().data();
+                        if child.geometry().contains(location) {
+                            let passthrough = match child {
+                                Data::Mapped { mapped, .. } => {
+                                    self.input_passthrough.contains(mapped)
+                                }
+                                Data::Group { .. } => false,
+                            };
+                            if passthrough {
+                                passed_through = Some(node.clone());
+                                continue;
+                            }
                             lookup = Some(child_id.clone());
                             break;
                         }
@@ -1781,8 +6725,12 @@ impl TilingLayout {
                     Data::Mapped {
                         mapped,
                         last_geometry,
+                        ..
                     },
                 )) => {
+                    if self.input_passthrough.contains(&mapped) {
+                        return None;
+                    }
                     let test_point = location.to_f64() - last_geometry.loc.to_f64()
                         + mapped.geometry().loc.to_f64();
                     mapped.is_in_input_region(&test_point).then(|| {
@@ -1792,14 +6740,8 @@ impl TilingLayout {
                         )
                     })
                 }
-                Some((
-                    id,
-                    Data::Group {
-                        orientation,
-                        last_geometry,
-                        ..
-                    },
-                )) => {
+                Some((id, Data::Group { .. })) if passed_through.as_ref() == Some(&id) => None,
+                Some((id, Data::Group { orientation, .. })) => {
                     let idx = tree
                         .children(&id)
                         .unwrap()
@@ -1811,46 +6753,147 @@ impl TilingLayout {
                             }
                         })
                         .and_then(|x| x.checked_sub(1))?;
-                    Some((
-                        ResizeForkTarget {
-                            node: id.clone(),
-                            output: output_data.output.downgrade(),
-                            left_up_idx: idx,
-                            orientation,
-                        }
-                        .into(),
-                        last_geometry.loc - output_data.location
-                            + tree
-                                .children(&id)
-                                .unwrap()
-                                .skip(idx)
-                                .next()
-                                .map(|node| {
-                                    let geo = node.data().geometry();
-                                    geo.loc + geo.size
-                                })
-                                .unwrap(),
-                    ))
+                    let target = ResizeForkTarget {
+                        node: id,
+                        output: output_data.output.downgrade(),
+                        left_up_idx: idx,
+                        orientation,
+                    };
+                    *last_fork_hit = Some(target.clone());
+                    Some(TilingLayout::fork_focus(tree, target, output_data))
                 }
                 _ => None,
             }
         })
     }
 
+    /// Like `element_under`, but for stacks also reports whether the tab bar or the content was hit
+    pub fn stack_hit_under(
+        &self,
+        location: Point<f64, Logical>,
+    ) -> Option<(CosmicMapped, StackHit)> {
+        let (PointerFocusTarget::Element(mapped), _) = self.element_under(location)? else {
+            return None;
+        };
+        let stack = mapped.stack_ref()?;
+        let (output_data, queue) = self
+            .queues
+            .get_key_value(self.output_for_element(&mapped)?)
+            .unwrap();
+        let geo = self.element_geometry(&mapped)?;
+
+        let mut local = location - output_data.location.to_f64();
+        if let Some(transform) = queue.overview_transform {
+            local = transform.invert(local);
+        }
+        let hit =
+            if local.y - ((geo.loc.y - output_data.location.y) as f64) < stack.offset().y as f64 {
+                StackHit::Header
+            } else {
+                StackHit::Surface(stack.active())
+            };
+        Some((mapped, hit))
+    }
+
+    /// Returns the output, if `location` is within its tiling area, but neither over a window nor a fork
+    pub fn is_over_gap(&self, location: Point<f64, Logical>) -> Option<Output> {
+        let (output_data, _) = self.queues.iter().find(|(output_data, queue)| {
+            let mut local = location - output_data.location.to_f64();
+            if let Some(transform) = queue.overview_transform {
+                local = transform.invert(local);
+            }
+            TilingLayout::tiling_area(
+                &output_data.output,
+                &self.config,
+                queue.overscan,
+                queue.reserved_zone,
+            )
+            .contains(local.to_i32_round())
+        })?;
+
+        self.element_under(location)
+            .is_none()
+            .then(|| output_data.output.clone())
+    }
+
+    // space between the children of a fork, if it still exists
+    fn fork_band(tree: &Tree<Data>, target: &ResizeForkTarget) -> Option<Rectangle<i32, Logical>> {
+        let group = tree.get(&target.node).ok()?;
+        if !group.data().is_group() || group.data().orientation() != target.orientation {
+            return None;
+        }
+        let group_geo = *group.data().geometry();
+        let mut children = tree
+            .children(&target.node)
+            .unwrap()
+            .skip(target.left_up_idx);
+        let first = *children.next()?.data().geometry();
+        let second = *children.next()?.data().geometry();
+        Some(match target.orientation {
+            Orientation::Vertical => Rectangle::from_loc_and_size(
+                (first.loc.x + first.size.w, group_geo.loc.y),
+                (
+                    second.loc.x - (first.loc.x + first.size.w),
+                    group_geo.size.h,
+                ),
+            ),
+            Orientation::Horizontal => Rectangle::from_loc_and_size(
+                (group_geo.loc.x, first.loc.y + first.size.h),
+                (
+                    group_geo.size.w,
+                    second.loc.y - (first.loc.y + first.size.h),
+                ),
+            ),
+        })
+    }
+
+    fn fork_focus(
+        tree: &Tree<Data>,
+        target: ResizeForkTarget,
+        output_data: &OutputData,
+    ) -> (PointerFocusTarget, Point<i32, Logical>) {
+        let group_loc = tree.get(&target.node).unwrap().data().geometry().loc;
+        let offset = tree
+            .children(&target.node)
+            .unwrap()
+            .skip(target.left_up_idx)
+            .next()
+            .map(|node| {
+                let geo = node.data().geometry();
+                geo.loc + geo.size
+            })
+            .unwrap();
+        (target.into(), group_loc - output_data.location + offset)
+    }
+
+    // windows are listed once, at their own node rather than their mirrors
+    fn is_listed(&self, tree: &Tree<Data>, data: &Data) -> bool {
+        match data {
+            Data::Mapped { mapped, .. } => {
+                !(self.sticky_windows.contains(mapped)
+                    && TilingLayout::is_sticky_mirror(tree, mapped))
+            }
+            Data::Group { .. } => false,
+        }
+    }
+
     pub fn mapped(
         &self,
     ) -> impl Iterator<Item = (&Output, &CosmicMapped, Rectangle<i32, Logical>)> {
+        let tree_order = self.config.render_order == RenderOrder::TreeOrder;
         self.queues
             .iter()
-            .flat_map(|(output_data, queue)| {
+            .flat_map(move |(output_data, queue)| {
                 let tree = &queue.trees.back().unwrap().0;
                 if let Some(root) = tree.root_node_id() {
                     Some(
                         tree.traverse_pre_order(root)
                             .unwrap()
-                            .filter(|node| node.data().is_mapped(None))
-                            .filter(|node| match node.data() {
-                                Data::Mapped { mapped, .. } => mapped.is_activated(false),
+                            .filter(move |node| self.is_listed(tree, node.data()))
+                            .filter(move |node| match node.data() {
+                                Data::Mapped { mapped, .. } => {
+                                    tree_order || mapped.is_activated(false)
+                                }
                                 _ => unreachable!(),
                             })
                             .map(|node| match node.data() {
@@ -1868,9 +6911,11 @@ impl TilingLayout {
                             .chain(
                                 tree.traverse_pre_order(root)
                                     .unwrap()
-                                    .filter(|node| node.data().is_mapped(None))
-                                    .filter(|node| match node.data() {
-                                        Data::Mapped { mapped, .. } => !mapped.is_activated(false),
+                                    .filter(move |node| self.is_listed(tree, node.data()))
+                                    .filter(move |node| match node.data() {
+                                        Data::Mapped { mapped, .. } => {
+                                            !tree_order && !mapped.is_activated(false)
+                                        }
                                         _ => unreachable!(),
                                     })
                                     .map(|node| match node.data() {
@@ -1913,56 +6958,271 @@ impl TilingLayout {
         for (output_data, mut src_queue) in other.queues {
             let src = src_queue.trees.pop_back().unwrap().0;
             let dst_queue = self.queues.entry(output_data.clone()).or_default();
+            dst_queue
+                .change_log
+                .get_or_insert_with(|| (output_data.output.clone(), self.changes.clone()));
             let mut dst = dst_queue.trees.back().unwrap().0.copy_clone();
 
             let orientation = match output_data.output.geometry().size {
                 x if x.w >= x.h => Orientation::Vertical,
                 _ => Orientation::Horizontal,
             };
-            TilingLayout::merge_trees(src, &mut dst, orientation);
-
-            let blocker = TilingLayout::update_positions(&output_data.output, &mut dst, self.gaps);
-            dst_queue.push_tree(dst, ANIMATION_DURATION, blocker);
+            TilingLayout::merge_trees(src, &mut dst, &output_data.output, orientation);
+
+            let blocker = TilingLayout::update_positions(
+                &output_data.output,
+                &mut dst,
+                &self.config,
+                self.geometry_hook.as_ref(),
+                dst_queue.overscan,
+                dst_queue.reserved_zone,
+            );
+            dst_queue.push_tree(dst, self.config.animation_duration, blocker);
         }
+        // their mirrors came along with the trees
+        self.sticky_windows.extend(other.sticky_windows);
     }
 
-    fn merge_trees(src: Tree<Data>, dst: &mut Tree<Data>, orientation: Orientation) {
-        if let Some(dst_root_id) = dst.root_node_id().cloned() {
-            let mut stack = Vec::new();
+    /// Depth of the deepest tree `merge`-ing `other` into this layout would create, without actually merging
+    pub fn merge_cost(&self, other: &TilingLayout) -> usize {
+        // number of groups above the deepest window
+        let depth = |tree: &Tree<Data>| {
+            tree.root_node_id().map(|root_id| {
+                tree.traverse_pre_order_ids(root_id)
+                    .unwrap()
+                    .map(|id| tree.ancestor_ids(&id).unwrap().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+        };
 
-            if let Some(src_root_id) = src.root_node_id() {
-                let root_node = src.get(src_root_id).unwrap();
-                let new_node = Node::new(root_node.data().clone());
-                let new_id = dst
-                    .insert(new_node, InsertBehavior::UnderNode(&dst_root_id))
-                    .unwrap();
-                if let &mut Data::Mapped { ref mut mapped, .. } =
-                    dst.get_mut(&new_id).unwrap().data_mut()
-                {
-                    *mapped.tiling_node_id.lock().unwrap() = Some(new_id.clone());
+        other
+            .queues
+            .iter()
+            .filter_map(|(output_data, src_queue)| {
+                let src_depth = depth(&src_queue.trees.back().unwrap().0)?;
+                let dst_depth = self
+                    .queues
+                    .get(&output_data.output)
+                    .and_then(|dst_queue| depth(&dst_queue.trees.back().unwrap().0));
+                // both trees end up below a new root group
+                Some(match dst_depth {
+                    Some(dst_depth) => dst_depth.max(src_depth) + 1,
+                    None => src_depth,
+                })
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn merge_trees(
+        src: Tree<Data>,
+        dst: &mut Tree<Data>,
+        output: &Output,
+        orientation: Orientation,
+    ) {
+        if dst.root_node_id().is_none() {
+            *dst = src;
+            return;
+        }
+
+        match TilingLayout::try_merge_trees(&src, dst, orientation) {
+            Ok((merged, node_ids)) => {
+                *dst = merged;
+                for (mapped, node_id) in node_ids {
+                    *mapped.tiling_node_id.lock().unwrap() = Some(node_id);
+                }
+            }
+            Err(err) => {
+                warn!(?err, "Failed to merge trees, mapping windows one by one.");
+                let windows = src
+                    .root_node_id()
+                    .and_then(|root_id| src.traverse_pre_order(root_id).ok())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|node| match node.data() {
+                        Data::Mapped { mapped, .. } => Some(mapped.clone()),
+                        Data::Group { .. } => None,
+                    })
+                    .collect::<Vec<_>>();
+                for mapped in windows {
+                    TilingLayout::map_to_tree(
+                        dst,
+                        mapped,
+                        output,
+                        None::<std::iter::Empty<&CosmicMapped>>,
+                        None,
+                        None,
+                        Some(orientation),
+                        0.5,
+                    );
                 }
-                TilingLayout::new_group(dst, &dst_root_id, &new_id, orientation).unwrap();
-                stack.push((src_root_id.clone(), new_id));
             }
+        }
+    }
 
-            while let Some((src_id, dst_id)) = stack.pop() {
-                for child_id in src.children_ids(&src_id).unwrap() {
-                    let src_node = src.get(&child_id).unwrap();
-                    let new_node = Node::new(src_node.data().clone());
-                    let new_child_id = dst
-                        .insert(new_node, InsertBehavior::UnderNode(&dst_id))
-                        .unwrap();
-                    if let &mut Data::Mapped { ref mut mapped, .. } =
-                        dst.get_mut(&new_child_id).unwrap().data_mut()
-                    {
-                        *mapped.tiling_node_id.lock().unwrap() = Some(new_child_id.clone());
-                    }
-                    stack.push((child_id.clone(), new_child_id));
+    // merges into a copy of `dst`, so nothing is touched on failure,
+    // the new node ids of the moved windows are returned instead of being set
+    fn try_merge_trees(
+        src: &Tree<Data>,
+        dst: &Tree<Data>,
+        orientation: Orientation,
+    ) -> Result<(Tree<Data>, Vec<(CosmicMapped, NodeId)>), NodeIdError> {
+        let mut dst = dst.copy_clone();
+        let mut node_ids = Vec::new();
+        let Some(dst_root_id) = dst.root_node_id().cloned() else {
+            return Ok((dst, node_ids));
+        };
+        let Some(src_root_id) = src.root_node_id() else {
+            return Ok((dst, node_ids));
+        };
+
+        let mut stack = Vec::new();
+        let root_data = src.get(src_root_id)?.data().clone();
+        let new_id = dst.insert(
+            Node::new(root_data.clone()),
+            InsertBehavior::UnderNode(&dst_root_id),
+        )?;
+        if let Data::Mapped { mapped, .. } = root_data {
+            node_ids.push((mapped, new_id.clone()));
+        }
+        TilingLayout::new_group(&mut dst, &dst_root_id, &new_id, orientation)?;
+        stack.push((src_root_id.clone(), new_id));
+
+        while let Some((src_id, dst_id)) = stack.pop() {
+            for child_id in src.children_ids(&src_id)? {
+                let data = src.get(&child_id)?.data().clone();
+                let new_child_id =
+                    dst.insert(Node::new(data.clone()), InsertBehavior::UnderNode(&dst_id))?;
+                if let Data::Mapped { mapped, .. } = data {
+                    node_ids.push((mapped, new_child_id.clone()));
                 }
+                stack.push((child_id.clone(), new_child_id));
             }
-        } else {
-            *dst = src;
         }
+
+        Ok((dst, node_ids))
+    }
+
+    /// Renders the committed layout of `output` scaled into `target_size`,
+    /// without any indicators or popups
+    pub fn render_thumbnail<R>(
+        &self,
+        renderer: &mut R,
+        output: &Output,
+        target_size: Size<i32, Logical>,
+    ) -> Vec<CosmicMappedRenderElement<R>>
+    where
+        R: Renderer + ImportAll + ImportMem + AsGlowRenderer,
+        <R as Renderer>::TextureId: 'static,
+        CosmicMappedRenderElement<R>: RenderElement<R>,
+        CosmicWindowRenderElement<R>: RenderElement<R>,
+        CosmicStackRenderElement<R>: RenderElement<R>,
+    {
+        let Some(queue) = self.queues.get(output) else { return Vec::new() };
+        let tree = &queue.trees.back().unwrap().0;
+        let Some(root) = tree.root_node_id() else { return Vec::new() };
+
+        let output_scale = output.current_scale().fractional_scale();
+        let area = *tree.get(root).unwrap().data().geometry();
+        let (scale, offset) =
+            scale_to_center(&area, &Rectangle::from_loc_and_size((0, 0), target_size));
+
+        let mut elements = Vec::new();
+        for node in tree.traverse_pre_order(root).unwrap() {
+            let Data::Mapped { mapped, last_geometry, .. } = node.data() else { continue };
+            let slot_loc = offset
+                + (last_geometry.loc - area.loc)
+                    .to_f64()
+                    .upscale(scale)
+                    .to_i32_round();
+            let original_location =
+                (last_geometry.loc - mapped.geometry().loc).to_physical_precise_round(output_scale);
+
+            let (w_elements, _) = mapped.split_render_elements::<R, CosmicMappedRenderElement<R>>(
+                renderer,
+                original_location,
+                Scale::from(output_scale),
+                1.0,
+            );
+            elements.extend(w_elements.into_iter().flat_map(|element| match element {
+                CosmicMappedRenderElement::Stack(elem) => {
+                    Some(CosmicMappedRenderElement::TiledStack({
+                        let cropped = CropRenderElement::from_element(
+                            elem,
+                            output_scale,
+                            snap_to_physical(*last_geometry, output_scale),
+                        )?;
+                        let rescaled = RescaleRenderElement::from_element(
+                            cropped,
+                            last_geometry.loc.to_physical_precise_round(output_scale),
+                            scale,
+                        );
+                        RelocateRenderElement::from_element(
+                            rescaled,
+                            (slot_loc - last_geometry.loc).to_physical_precise_round(output_scale),
+                            Relocate::Relative,
+                        )
+                    }))
+                }
+                CosmicMappedRenderElement::Window(elem) => {
+                    Some(CosmicMappedRenderElement::TiledWindow({
+                        let cropped = CropRenderElement::from_element(
+                            elem,
+                            output_scale,
+                            snap_to_physical(*last_geometry, output_scale),
+                        )?;
+                        let rescaled = RescaleRenderElement::from_element(
+                            cropped,
+                            last_geometry.loc.to_physical_precise_round(output_scale),
+                            scale,
+                        );
+                        RelocateRenderElement::from_element(
+                            rescaled,
+                            (slot_loc - last_geometry.loc).to_physical_precise_round(output_scale),
+                            Relocate::Relative,
+                        )
+                    }))
+                }
+                x => Some(x),
+            }));
+        }
+
+        elements
+    }
+
+    // thins the indicator right after focus moved and eases it back to `thickness`
+    fn pulsed_indicator_thickness(
+        &self,
+        queue: &TreeQueue,
+        tree: &Tree<Data>,
+        seat: Option<&Seat<State>>,
+        thickness: u8,
+    ) -> u8 {
+        let Some(duration) = self.config.focus_pulse_duration.filter(|_| thickness > 0) else {
+            return thickness;
+        };
+        let Some(focused) = seat
+            .and_then(|seat| {
+                let target = seat.get_keyboard().unwrap().current_focus()?;
+                TilingLayout::currently_focused_node(tree, &seat.active_output(), target)
+            })
+            .map(|(id, _)| id) else { return thickness };
+
+        let mut pulse = queue.focus_pulse.lock().unwrap();
+        let start = match pulse.as_ref() {
+            Some((node, start)) if node == &focused => *start,
+            _ => {
+                let now = Instant::now();
+                *pulse = Some((focused, now));
+                now
+            }
+        };
+        let percentage = self
+            .config
+            .animation_curve
+            .apply(start.elapsed().as_millis() as f32 / duration.as_millis() as f32);
+        1 + (thickness.saturating_sub(1) as f32 * percentage).round() as u8
     }
 
     pub fn render_output<R>(
@@ -1974,6 +7234,9 @@ impl TilingLayout {
         overview: OverviewMode,
         resize_indicator: Option<(ResizeMode, ResizeIndicator)>,
         indicator_thickness: u8,
+        backdrop_color: Option<[f32; 4]>,
+        drop_hint: Option<&DropHint>,
+        show_group_labels: bool,
     ) -> Result<
         (
             Vec<CosmicMappedRenderElement<R>>,
@@ -1993,11 +7256,9 @@ impl TilingLayout {
 
         let output_scale = output.current_scale().fractional_scale();
 
-        if !self.queues.contains_key(output) {
+        let Some((output_data, queue)) = self.queues.get_key_value(output) else {
             return Err(OutputNotMapped);
-        }
-
-        let queue = self.queues.get(output).unwrap();
+        };
         let (target_tree, duration, _) = if queue.animation_start.is_some() {
             queue
                 .trees
@@ -2014,7 +7275,7 @@ impl TilingLayout {
         let percentage = if let Some(animation_start) = queue.animation_start {
             let percentage = Instant::now().duration_since(animation_start).as_millis() as f32
                 / duration.as_millis() as f32;
-            ease(EaseInOutCubic, 0.0, 1.0, percentage)
+            self.config.animation_curve.apply(percentage)
         } else {
             1.0
         };
@@ -2034,6 +7295,8 @@ impl TilingLayout {
                     // but for that we have to associate focus with a tree (and animate focus changes properly)
                     1.0 - transition,
                     transition,
+                    show_group_labels,
+                    queue.overview_transform,
                 )
             } else {
                 None
@@ -2065,6 +7328,8 @@ impl TilingLayout {
                 seat,
                 transition,
                 transition,
+                show_group_labels,
+                queue.overview_transform,
             )
         } else {
             None
@@ -2089,9 +7354,10 @@ impl TilingLayout {
                     indicator_thickness - diff
                 }
             } else {
-                indicator_thickness
+                self.pulsed_indicator_thickness(queue, target_tree, seat, indicator_thickness)
             },
             resize_indicator,
+            self.config.render_order,
         );
         window_elements.extend(w_elements);
         popup_elements.extend(p_elements);
@@ -2101,12 +7367,200 @@ impl TilingLayout {
             window_elements.extend(group_elements);
         }
 
+        if let Some((node_id, _)) = seat
+            .and_then(|seat| self.group_selection.get(seat))
+            .map(|(_, node_id, selection_output)| (node_id, selection_output))
+            .filter(|(_, selection_output)| *selection_output == output)
+        {
+            if let Ok(Data::Group {
+                alive,
+                last_geometry,
+                ..
+            }) = target_tree.get(node_id).map(|node| node.data())
+            {
+                window_elements.insert(
+                    0,
+                    IndicatorShader::element(
+                        renderer,
+                        Key::Group(Arc::downgrade(alive)),
+                        *last_geometry,
+                        4,
+                        8,
+                        1.0,
+                        GROUP_COLOR,
+                    )
+                    .into(),
+                );
+            }
+        }
+
+        if let Some((node_id, direction)) = queue.preselect.as_ref() {
+            if let Ok(node) = target_tree.get(node_id) {
+                let mut geo = *node.data().geometry();
+                match direction {
+                    Direction::Left => geo.size.w /= 2,
+                    Direction::Right => {
+                        geo.loc.x += geo.size.w / 2;
+                        geo.size.w -= geo.size.w / 2;
+                    }
+                    Direction::Up => geo.size.h /= 2,
+                    Direction::Down => {
+                        geo.loc.y += geo.size.h / 2;
+                        geo.size.h -= geo.size.h / 2;
+                    }
+                }
+                window_elements.insert(
+                    0,
+                    IndicatorShader::element(
+                        renderer,
+                        output_data.preselect_id.clone(),
+                        geo,
+                        4,
+                        8,
+                        0.6,
+                        GROUP_COLOR,
+                    )
+                    .into(),
+                );
+            }
+        }
+
+        if let Some(geo) = drop_hint.and_then(|hint| drop_hint_geometry(target_tree, hint)) {
+            window_elements.insert(
+                0,
+                IndicatorShader::element(
+                    renderer,
+                    output_data.drop_hint_id.clone(),
+                    geo,
+                    4,
+                    8,
+                    0.6,
+                    GROUP_COLOR,
+                )
+                .into(),
+            );
+        }
+
+        // backdrop below everything else
+        if let Some(color) = backdrop_color {
+            window_elements.push(
+                BackdropShader::element(
+                    renderer,
+                    output_data.backdrop_id.clone(),
+                    non_exclusive_zone,
+                    0.,
+                    color[3],
+                    [color[0], color[1], color[2]],
+                )
+                .into(),
+            );
+        }
+
         Ok((window_elements, popup_elements))
     }
 }
 
 const OUTER_GAP: i32 = 8;
 const INNER_GAP: i32 = 16;
+const LABEL_DIGIT_WIDTH: i32 = 10;
+const LABEL_DIGIT_HEIGHT: i32 = 18;
+const LABEL_STROKE: i32 = 2;
+
+/// Seven segment rectangles spelling out `number`, relative to the label's origin
+fn label_segments(number: usize) -> Vec<Rectangle<i32, Logical>> {
+    // segments a to g, one bit each
+    const DIGITS: [u8; 10] = [
+        0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+        0b1111111, 0b1101111,
+    ];
+    let (w, h, t) = (LABEL_DIGIT_WIDTH, LABEL_DIGIT_HEIGHT, LABEL_STROKE);
+    let half = h / 2;
+    let segments = [
+        ((0, 0), (w, t)),
+        ((w - t, 0), (t, half)),
+        ((w - t, half), (t, h - half)),
+        ((0, h - t), (w, t)),
+        ((0, half), (t, h - half)),
+        ((0, 0), (t, half)),
+        ((0, half - t / 2), (w, t)),
+    ];
+
+    number
+        .to_string()
+        .bytes()
+        .enumerate()
+        .flat_map(|(pos, digit)| {
+            let mask = DIGITS[(digit - b'0') as usize];
+            let offset = pos as i32 * (w + 4);
+            segments
+                .into_iter()
+                .enumerate()
+                .filter(move |(i, _)| mask & (1 << i) != 0)
+                .map(move |(_, (loc, size))| {
+                    Rectangle::from_loc_and_size((loc.0 + offset, loc.1), size)
+                })
+        })
+        .collect()
+}
+
+fn split_orientation(size: Size<i32, Logical>, preferred_aspect: Option<f64>) -> Orientation {
+    match preferred_aspect {
+        Some(aspect) => {
+            // compare ratios logarithmically, so 2:1 and 1:2 are equally far from 1:1
+            let vertical = (size.w as f64 / 2.0) / size.h as f64;
+            let horizontal = size.w as f64 / (size.h as f64 / 2.0);
+            if (vertical.ln() - aspect.ln()).abs() <= (horizontal.ln() - aspect.ln()).abs() {
+                Orientation::Vertical
+            } else {
+                Orientation::Horizontal
+            }
+        }
+        None => {
+            if size.w > size.h {
+                Orientation::Vertical
+            } else {
+                Orientation::Horizontal
+            }
+        }
+    }
+}
+
+fn drop_hint_geometry(tree: &Tree<Data>, hint: &DropHint) -> Option<Rectangle<i32, Logical>> {
+    let node = tree.get(&hint.node).ok()?;
+    let children = tree
+        .children(&hint.node)
+        .ok()?
+        .map(|child| *child.data().geometry())
+        .collect::<Vec<_>>();
+
+    // inserting into a matching group splits the neighbouring child,
+    // anything else splits the target node itself
+    let (mut geo, leading) = if children.is_empty() || node.data().orientation() != hint.orientation
+    {
+        (*node.data().geometry(), hint.idx == 0)
+    } else if hint.idx < children.len() {
+        (children[hint.idx], true)
+    } else {
+        (*children.last().unwrap(), false)
+    };
+
+    match hint.orientation {
+        Orientation::Vertical => {
+            geo.size.w /= 2;
+            if !leading {
+                geo.loc.x += geo.size.w;
+            }
+        }
+        Orientation::Horizontal => {
+            geo.size.h /= 2;
+            if !leading {
+                geo.loc.y += geo.size.h;
+            }
+        }
+    }
+
+    Some(geo)
+}
 
 fn geometries_for_groupview<R>(
     tree: &Tree<Data>,
@@ -2115,6 +7569,8 @@ fn geometries_for_groupview<R>(
     seat: Option<&Seat<State>>,
     alpha: f32,
     transition: f32,
+    show_labels: bool,
+    transform: Option<ViewTransform>,
 ) -> Option<(
     HashMap<NodeId, Rectangle<i32, Logical>>,
     Vec<CosmicMappedRenderElement<R>>,
@@ -2138,6 +7594,10 @@ where
         let mut elements = Vec::new();
         let mut geometries = HashMap::new();
         let alpha = alpha * transition;
+        let view = |rect| match transform {
+            Some(transform) => transform.apply(rect),
+            None => rect,
+        };
 
         let focused = seat
             .and_then(|seat| {
@@ -2162,6 +7622,7 @@ where
             false
         };
 
+        let mut group_number = 0;
         for node_id in tree.traverse_pre_order_ids(root).unwrap() {
             if let Some(mut geo) = stack.pop() {
                 let node: &Node<Data> = tree.get(&node_id).unwrap();
@@ -2203,7 +7664,9 @@ where
                         last_geometry,
                         sizes,
                         alive,
+                        ..
                     } => {
+                        group_number += 1;
                         let render_active_child = if let Some(focused_id) = focused.as_ref() {
                             !has_potential_groups
                                 && node
@@ -2219,7 +7682,7 @@ where
                                 IndicatorShader::element(
                                     renderer,
                                     Key::Group(Arc::downgrade(alive)),
-                                    geo,
+                                    view(geo),
                                     4,
                                     if render_active_child { 16 } else { 8 },
                                     alpha * if render_potential_group { 0.40 } else { 1.0 },
@@ -2229,7 +7692,48 @@ where
                             );
                         }
 
-                        geometries.insert(node_id.clone(), geo);
+                        // the group's number and a small glyph showing its orientation
+                        if show_labels {
+                            let origin = geo.loc + Point::from((8, 8));
+                            for (i, segment) in label_segments(group_number).into_iter().enumerate()
+                            {
+                                elements.push(
+                                    BackdropShader::element(
+                                        renderer,
+                                        Key::GroupLabel(Arc::downgrade(alive), i + 1),
+                                        view(Rectangle::from_loc_and_size(
+                                            origin + segment.loc,
+                                            segment.size,
+                                        )),
+                                        1.,
+                                        alpha * 0.8,
+                                        GROUP_COLOR,
+                                    )
+                                    .into(),
+                                );
+                            }
+
+                            let size = match orientation {
+                                Orientation::Vertical => (24, 8),
+                                Orientation::Horizontal => (8, 24),
+                            };
+                            elements.push(
+                                BackdropShader::element(
+                                    renderer,
+                                    Key::GroupLabel(Arc::downgrade(alive), 0),
+                                    view(Rectangle::from_loc_and_size(
+                                        origin + Point::from((0, LABEL_DIGIT_HEIGHT + 4)),
+                                        size,
+                                    )),
+                                    4.,
+                                    alpha * 0.8,
+                                    GROUP_COLOR,
+                                )
+                                .into(),
+                            );
+                        }
+
+                        geometries.insert(node_id.clone(), view(geo));
 
                         let previous_length = match orientation {
                             Orientation::Horizontal => last_geometry.size.h,
@@ -2284,7 +7788,7 @@ where
                                 IndicatorShader::element(
                                     renderer,
                                     mapped.clone(),
-                                    geo,
+                                    view(geo),
                                     4,
                                     8,
                                     alpha * if render_potential_group { 0.40 } else { 1.0 },
@@ -2311,7 +7815,7 @@ where
                                 BackdropShader::element(
                                     renderer,
                                     mapped.clone(),
-                                    geo,
+                                    view(geo),
                                     8.,
                                     alpha
                                         * if focused
@@ -2332,7 +7836,7 @@ where
                         geo.loc += (inner_gap, inner_gap).into();
                         geo.size -= (inner_gap * 2, inner_gap * 2).into();
 
-                        geometries.insert(node_id.clone(), geo);
+                        geometries.insert(node_id.clone(), view(geo));
                     }
                 }
             }
@@ -2428,7 +7932,7 @@ where
                             let cropped = CropRenderElement::from_element(
                                 elem,
                                 output_scale,
-                                crop_rect.to_physical_precise_round(output_scale),
+                                snap_to_physical(crop_rect, output_scale),
                             )?;
                             let rescaled = RescaleRenderElement::from_element(
                                 cropped,
@@ -2449,7 +7953,7 @@ where
                             let cropped = CropRenderElement::from_element(
                                 elem,
                                 output_scale,
-                                crop_rect.to_physical_precise_round(output_scale),
+                                snap_to_physical(crop_rect, output_scale),
                             )?;
                             let rescaled = RescaleRenderElement::from_element(
                                 cropped,
@@ -2485,6 +7989,7 @@ fn render_new_tree<R>(
     percentage: f32,
     indicator_thickness: u8,
     mut resize_indicator: Option<(ResizeMode, ResizeIndicator)>,
+    render_order: RenderOrder,
 ) -> (
     Vec<CosmicMappedRenderElement<R>>,
     Vec<CosmicMappedRenderElement<R>>,
@@ -2512,6 +8017,8 @@ where
         .map(|(id, _)| id);
 
     let mut window_elements = Vec::new();
+    let mut active_elements = Vec::new();
+    let mut on_top_elements = Vec::new();
     let mut popup_elements = Vec::new();
 
     let mut group_backdrop = None;
@@ -2685,65 +8192,88 @@ where
                     }
                 }
 
-                if let Data::Mapped { mapped, .. } = data {
+                if let Data::Mapped {
+                    mapped,
+                    always_on_top,
+                    ..
+                } = data
+                {
                     let original_location = (original_geo.loc - mapped.geometry().loc)
                         .to_physical_precise_round(output_scale);
 
-                    let (w_elements, p_elements) = mapped
+                    let (w_elements, mut p_elements) = mapped
                         .split_render_elements::<R, CosmicMappedRenderElement<R>>(
                             renderer,
                             original_location,
                             Scale::from(output_scale),
                             alpha,
                         );
+                    if geo.loc != original_geo.loc {
+                        // popups aren't cropped or rescaled, so just render them where the window is right now
+                        p_elements = mapped
+                            .split_render_elements::<R, CosmicMappedRenderElement<R>>(
+                                renderer,
+                                (geo.loc - mapped.geometry().loc)
+                                    .to_physical_precise_round(output_scale),
+                                Scale::from(output_scale),
+                                alpha,
+                            )
+                            .1;
+                    }
 
-                    window_elements.extend(w_elements.into_iter().flat_map(
-                        |element| match element {
-                            CosmicMappedRenderElement::Stack(elem) => {
-                                Some(CosmicMappedRenderElement::TiledStack({
-                                    let cropped = CropRenderElement::from_element(
-                                        elem,
-                                        output_scale,
-                                        crop_rect.to_physical_precise_round(output_scale),
-                                    )?;
-                                    let rescaled = RescaleRenderElement::from_element(
-                                        cropped,
-                                        original_geo.loc.to_physical_precise_round(output_scale),
-                                        scale,
-                                    );
-                                    let relocated = RelocateRenderElement::from_element(
-                                        rescaled,
-                                        (geo.loc - original_geo.loc)
-                                            .to_physical_precise_round(output_scale),
-                                        Relocate::Relative,
-                                    );
-                                    relocated
-                                }))
-                            }
-                            CosmicMappedRenderElement::Window(elem) => {
-                                Some(CosmicMappedRenderElement::TiledWindow({
-                                    let cropped = CropRenderElement::from_element(
-                                        elem,
-                                        output_scale,
-                                        crop_rect.to_physical_precise_round(output_scale),
-                                    )?;
-                                    let rescaled = RescaleRenderElement::from_element(
-                                        cropped,
-                                        original_geo.loc.to_physical_precise_round(output_scale),
-                                        scale,
-                                    );
-                                    let relocated = RelocateRenderElement::from_element(
-                                        rescaled,
-                                        (geo.loc - original_geo.loc)
-                                            .to_physical_precise_round(output_scale),
-                                        Relocate::Relative,
-                                    );
-                                    relocated
-                                }))
-                            }
-                            x => Some(x),
-                        },
-                    ));
+                    let elements = if *always_on_top {
+                        &mut on_top_elements
+                    } else if render_order == RenderOrder::ActiveLast && mapped.is_activated(false)
+                    {
+                        &mut active_elements
+                    } else {
+                        &mut window_elements
+                    };
+                    elements.extend(w_elements.into_iter().flat_map(|element| match element {
+                        CosmicMappedRenderElement::Stack(elem) => {
+                            Some(CosmicMappedRenderElement::TiledStack({
+                                let cropped = CropRenderElement::from_element(
+                                    elem,
+                                    output_scale,
+                                    snap_to_physical(crop_rect, output_scale),
+                                )?;
+                                let rescaled = RescaleRenderElement::from_element(
+                                    cropped,
+                                    original_geo.loc.to_physical_precise_round(output_scale),
+                                    scale,
+                                );
+                                let relocated = RelocateRenderElement::from_element(
+                                    rescaled,
+                                    (geo.loc - original_geo.loc)
+                                        .to_physical_precise_round(output_scale),
+                                    Relocate::Relative,
+                                );
+                                relocated
+                            }))
+                        }
+                        CosmicMappedRenderElement::Window(elem) => {
+                            Some(CosmicMappedRenderElement::TiledWindow({
+                                let cropped = CropRenderElement::from_element(
+                                    elem,
+                                    output_scale,
+                                    snap_to_physical(crop_rect, output_scale),
+                                )?;
+                                let rescaled = RescaleRenderElement::from_element(
+                                    cropped,
+                                    original_geo.loc.to_physical_precise_round(output_scale),
+                                    scale,
+                                );
+                                let relocated = RelocateRenderElement::from_element(
+                                    rescaled,
+                                    (geo.loc - original_geo.loc)
+                                        .to_physical_precise_round(output_scale),
+                                    Relocate::Relative,
+                                );
+                                relocated
+                            }))
+                        }
+                        x => Some(x),
+                    }));
                     popup_elements.extend(p_elements)
                 }
             });
@@ -2752,6 +8282,8 @@ where
             .into_iter()
             .flatten()
             .chain(indicator.into_iter().map(Into::into))
+            .chain(on_top_elements)
+            .chain(active_elements)
             .chain(window_elements)
             .chain(group_backdrop.into_iter().map(Into::into))
             .collect();
@@ -2760,6 +8292,16 @@ where
     (window_elements, popup_elements)
 }
 
+// Rounds the edges instead of location and size separately,
+// so tiles sharing a logical edge also share the physical one at fractional scales.
+fn snap_to_physical(rect: Rectangle<i32, Logical>, scale: f64) -> Rectangle<i32, Physical> {
+    let x1 = (rect.loc.x as f64 * scale).round() as i32;
+    let y1 = (rect.loc.y as f64 * scale).round() as i32;
+    let x2 = ((rect.loc.x + rect.size.w) as f64 * scale).round() as i32;
+    let y2 = ((rect.loc.y + rect.size.h) as f64 * scale).round() as i32;
+    Rectangle::from_loc_and_size((x1, y1), (x2 - x1, y2 - y1))
+}
+
 fn scale_to_center(
     old_geo: &Rectangle<i32, Logical>,
     new_geo: &Rectangle<i32, Logical>,
@@ -1331,14 +1331,16 @@ impl Shell {
         {
             mapped.set_debug(state.common.egui.active);
         }
-        if layout::should_be_floating(&window) || !workspace.tiling_enabled {
+        // the window might have joined an existing stack
+        let mapped = if layout::should_be_floating(&window) || !workspace.tiling_enabled {
             workspace.floating_layer.map(mapped.clone(), &seat, None);
+            mapped
         } else {
             let focus_stack = workspace.focus_stack.get(&seat);
             workspace
                 .tiling_layer
-                .map(mapped.clone(), &seat, focus_stack.iter(), None);
-        }
+                .map(mapped, &seat, focus_stack.iter(), None, None)
+        };
 
         if let CosmicSurface::X11(surface) = window {
             if let Some(xwm) = state
@@ -1399,7 +1401,7 @@ impl Shell {
             map.map_layer(&layer_surface).unwrap();
         }
         for workspace in state.common.shell.workspaces.spaces_mut() {
-            workspace.tiling_layer.recalculate(&output);
+            workspace.tiling_layer.recalculate(&output, false);
         }
 
         if wants_focus {
@@ -1472,13 +1474,18 @@ impl Shell {
             .get_mut(to_idx, to_output)
             .unwrap(); // checked above
         let focus_stack = to_workspace.focus_stack.get(&seat);
-        if window_state == ManagedState::Floating {
+        let focus_target = if window_state == ManagedState::Floating {
             to_workspace.floating_layer.map(mapped.clone(), &seat, None);
+            mapped.clone()
         } else {
-            to_workspace
-                .tiling_layer
-                .map(mapped.clone(), &seat, focus_stack.iter(), direction);
-        }
+            to_workspace.tiling_layer.map(
+                mapped.clone(),
+                &seat,
+                focus_stack.iter(),
+                direction,
+                None,
+            )
+        };
         for (toplevel, _) in mapped.windows() {
             if from_output != to_output {
                 state
@@ -1503,7 +1510,12 @@ impl Shell {
         }
 
         if follow {
-            Common::set_focus(state, Some(&KeyboardFocusTarget::from(mapped)), &seat, None);
+            Common::set_focus(
+                state,
+                Some(&KeyboardFocusTarget::from(focus_target)),
+                &seat,
+                None,
+            );
         }
         Ok(new_pos)
     }
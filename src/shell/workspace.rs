@@ -293,7 +293,7 @@ impl Workspace {
             window.set_maximized(false);
             window.set_fullscreen(false);
             self.floating_layer.refresh();
-            self.tiling_layer.recalculate(output);
+            self.tiling_layer.recalculate(output, true);
             self.tiling_layer.refresh();
             window.send_configure();
             self.fullscreen.retain(|_, w| w != window);
@@ -423,7 +423,7 @@ impl Workspace {
             {
                 self.floating_layer.unmap(&window);
                 self.tiling_layer
-                    .map(window, seat, focus_stack.iter(), None)
+                    .map(window, seat, focus_stack.iter(), None, None);
             }
             self.tiling_enabled = true;
         }
@@ -439,7 +439,7 @@ impl Workspace {
                     let focus_stack = self.focus_stack.get(seat);
                     self.floating_layer.unmap(&window);
                     self.tiling_layer
-                        .map(window, seat, focus_stack.iter(), None)
+                        .map(window, seat, focus_stack.iter(), None, None);
                 }
             }
         }
@@ -493,6 +493,8 @@ impl Workspace {
         overview: OverviewMode,
         resize_indicator: Option<(ResizeMode, ResizeIndicator)>,
         indicator_thickness: u8,
+        tiling_backdrop: Option<[f32; 4]>,
+        tiling_group_labels: bool,
     ) -> Result<
         (
             Vec<WorkspaceRenderElement<R>>,
@@ -624,6 +626,9 @@ impl Workspace {
                 overview.clone(),
                 resize_indicator,
                 indicator_thickness,
+                tiling_backdrop,
+                None,
+                tiling_group_labels,
             )?;
             popup_elements.extend(p_elements.into_iter().map(WorkspaceRenderElement::from));
             window_elements.extend(w_elements.into_iter().map(WorkspaceRenderElement::from));
@@ -251,7 +251,7 @@ impl CompositorHandler for State {
             let changed = layer_map_for_output(&output).arrange();
             if changed {
                 for workspace in self.common.shell.workspaces.spaces_mut() {
-                    workspace.tiling_layer.recalculate(&output);
+                    workspace.tiling_layer.handle_layer_zone_change(&output);
                 }
             }
         }